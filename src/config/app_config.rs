@@ -11,6 +11,12 @@ pub struct AppConfig {
     pub is_dev: bool,
     pub client_origin: String,
     pub server_addr: String,
+    // how long a started webauthn registration/login may take to finish
+    pub ceremony_ttl_secs: u64,
+    // how often scheduled polls are checked for opening/closing
+    pub poll_schedule_interval_secs: u64,
+    // days a deleted poll stays in the trash before it's purged
+    pub trash_retention_days: u64,
 }
 
 impl AppConfig {
@@ -45,6 +51,18 @@ impl AppConfig {
             error!("jwt_secret var not set!");
             String::from("Garden")
         });
+        let ceremony_ttl_secs = env::var("CEREMONY_TTL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(300);
+        let poll_schedule_interval_secs = env::var("POLL_SCHEDULE_INTERVAL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(5);
+        let trash_retention_days = env::var("POLL_TRASH_RETENTION_DAYS")
+            .ok()
+            .and_then(|days| days.parse().ok())
+            .unwrap_or(30);
         Self {
             db_url,
             is_dev,
@@ -54,6 +72,9 @@ impl AppConfig {
             token_secret,
             client_origin,
             server_addr,
+            ceremony_ttl_secs,
+            poll_schedule_interval_secs,
+            trash_retention_days,
         }
     }
 }
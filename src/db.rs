@@ -1,53 +1,171 @@
-use std::sync::{Arc, Mutex};
+use std::{sync::Arc, time::Duration};
 
 use crate::config::app_config::AppConfig;
 use auth_state_repo::AuthStateRepo;
+use credentials_repo::CredentialRepo;
+use invites_repo::InviteRepo;
 use log::error;
 use mongodb::Client;
 use options_repo::OptionRepo;
 use polls_repo::PollRepo;
+use receipts_repo::ReceiptRepo;
 use reg_state_repo::RegStateRepo;
+use secret_ballots_repo::SecretBallotRepo;
+use sessions_repo::SessionRepo;
 use tokio::try_join;
 use users_repo::UserRepo;
+use votes_repo::VoteRepo;
 pub mod auth_state_repo;
+pub mod credentials_repo;
+pub mod invites_repo;
 pub mod options_repo;
 pub mod polls_repo;
+pub mod receipts_repo;
 pub mod reg_state_repo;
+pub mod secret_ballots_repo;
+pub mod sessions_repo;
 pub mod users_repo;
+pub mod votes_repo;
 
+// Every repository wraps a mongodb `Collection`, which is already a cheap,
+// thread safe handle onto the client's connection pool, so the whole struct
+// is shared between workers as `Data<DB>` without any locking.
 pub struct DB {
     pub client: Client,
     pub reg_states: RegStateRepo,
     pub users: UserRepo,
     pub auth_states: AuthStateRepo,
+    pub credentials: CredentialRepo,
+    pub sessions: SessionRepo,
     pub options: OptionRepo,
     pub polls: PollRepo,
+    pub votes: VoteRepo,
+    pub invites: InviteRepo,
+    pub secret_ballots: SecretBallotRepo,
+    pub receipts: ReceiptRepo,
 }
 
 impl DB {
-    pub async fn init(app_config: Arc<AppConfig>) -> Result<Arc<Mutex<Self>>, ()> {
-        let mongo_uri = &app_config.db_url;
+    pub async fn init(app_config: Arc<AppConfig>) -> Result<Self, ()> {
+        let ceremony_ttl = Duration::from_secs(app_config.ceremony_ttl_secs);
+        Self::connect(&app_config.db_url, "polling-app", ceremony_ttl).await
+    }
+
+    pub async fn connect(
+        mongo_uri: &str,
+        db_name: &str,
+        ceremony_ttl: Duration,
+    ) -> Result<Self, ()> {
         let client = Client::with_uri_str(mongo_uri)
             .await
             .expect("Failed connecting to the database");
         println!("Connected to database!");
-        let database = client.database("polling-app");
-        let (reg_states, auth_states, users, options, polls) = try_join!(
-            RegStateRepo::init(&database),
-            AuthStateRepo::init(&database),
+        let database = client.database(db_name);
+        let (
+            reg_states,
+            auth_states,
+            users,
+            credentials,
+            sessions,
+            options,
+            polls,
+            votes,
+            invites,
+            secret_ballots,
+            receipts,
+        ) = try_join!(
+            RegStateRepo::init(&database, ceremony_ttl),
+            AuthStateRepo::init(&database, ceremony_ttl),
             UserRepo::init(&database),
+            CredentialRepo::init(&database),
+            SessionRepo::init(&database),
             OptionRepo::init(&database),
-            PollRepo::init(&database)
+            PollRepo::init(&database),
+            VoteRepo::init(&database),
+            InviteRepo::init(&database),
+            SecretBallotRepo::init(&database),
+            ReceiptRepo::init(&database)
         )
         .map_err(|e| error!("Error initializing collection: {}", e))?;
+        credentials
+            .migrate_legacy_passkeys(&database)
+            .await
+            .map_err(|e| error!("Error migrating legacy passkeys: {}", e))?;
+        votes
+            .migrate_legacy_voters(&database)
+            .await
+            .map_err(|e| error!("Error migrating legacy voters: {}", e))?;
         let db_instance = DB {
             client,
             reg_states,
             users,
             auth_states,
+            credentials,
+            sessions,
             options,
             polls,
+            votes,
+            invites,
+            secret_ballots,
+            receipts,
         };
-        Ok(Arc::new(Mutex::new(db_instance)))
+        Ok(db_instance)
+    }
+}
+
+#[cfg(test)]
+pub async fn test_db() -> DB {
+    let mongo_uri = std::env::var("TEST_DB_URL").expect("Set TEST_DB_URL to run db tests");
+    DB::connect(&mongo_uri, "polling-app-test", Duration::from_secs(300))
+        .await
+        .expect("Failed initializing test db")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::join_all;
+    use std::time::Instant;
+
+    // A benchmark rather than a test, it prints throughput and checks nothing.
+    // The lock only stands in for the old global mutex, so compare the numbers
+    // with care. Needs a running MongoDB:
+    // TEST_DB_URL=mongodb://localhost:27017 cargo test -- --ignored --nocapture
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    #[ignore]
+    async fn shared_repositories_serve_requests_concurrently() {
+        const REQUESTS: usize = 200;
+        let db = Arc::new(test_db().await);
+
+        // every call queued up behind one lock
+        let global_lock = Arc::new(tokio::sync::Mutex::new(()));
+        let started = Instant::now();
+        join_all((0..REQUESTS).map(|_| {
+            let db = db.clone();
+            let global_lock = global_lock.clone();
+            tokio::spawn(async move {
+                let _guard = global_lock.lock().await;
+                db.polls.get_live_polls(1, 10).await.unwrap();
+            })
+        }))
+        .await;
+        let serialized = started.elapsed();
+
+        let started = Instant::now();
+        join_all((0..REQUESTS).map(|_| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                db.polls.get_live_polls(1, 10).await.unwrap();
+            })
+        }))
+        .await;
+        let shared = started.elapsed();
+
+        println!(
+            "{} requests: {:.0} req/s behind a global lock, {:.0} req/s shared",
+            REQUESTS,
+            REQUESTS as f64 / serialized.as_secs_f64(),
+            REQUESTS as f64 / shared.as_secs_f64()
+        );
     }
 }
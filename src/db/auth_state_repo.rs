@@ -1,9 +1,9 @@
-use std::error::Error;
+use std::{error::Error, time::Duration};
 
 use anyhow::Result;
-use log::{debug, error};
+use log::{debug, error, info};
 use mongodb::{
-    bson::doc,
+    bson::{self, doc},
     results::{DeleteResult, InsertOneResult},
     Collection, Database, IndexModel,
 };
@@ -12,14 +12,38 @@ use serde::{Deserialize, Serialize};
 pub struct AuthState {
     pub username: String,
     pub auth_state: serde_json::Value,
+    pub created_at: bson::DateTime,
+}
+
+// Usernameless (discoverable) logins don't know who is signing in until the
+// ceremony finishes, so their state is keyed by a server issued challenge id.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DiscoverableAuthState {
+    pub challenge_id: String,
+    pub auth_state: serde_json::Value,
+    pub created_at: bson::DateTime,
 }
 
 pub struct AuthStateRepo {
     pub collection: Collection<AuthState>,
+    pub discoverable_collection: Collection<DiscoverableAuthState>,
+    ttl: Duration,
+}
+
+fn ttl_index(ttl: Duration) -> IndexModel {
+    IndexModel::builder()
+        .keys(doc! {"created_at": 1})
+        .options(
+            mongodb::options::IndexOptions::builder()
+                .expire_after(Some(ttl))
+                .name(Some("expire_auth_states".to_string()))
+                .build(),
+        )
+        .build()
 }
 
 impl AuthStateRepo {
-    pub async fn init(db: &Database) -> Result<Self, Box<dyn Error>> {
+    pub async fn init(db: &Database, ttl: Duration) -> Result<Self, Box<dyn Error>> {
         let auth_state_collection: Collection<AuthState> = db.collection("auth_states");
 
         let index = IndexModel::builder()
@@ -36,20 +60,68 @@ impl AuthStateRepo {
             error!("Failed to create index on `username`: {:?}", e);
         }
 
+        let discoverable_collection: Collection<DiscoverableAuthState> =
+            db.collection("discoverable_auth_states");
+        let challenge_index = IndexModel::builder()
+            .keys(doc! {"challenge_id": 1})
+            .options(
+                mongodb::options::IndexOptions::builder()
+                    .unique(true)
+                    .name(Some("unique_challenge_id".to_string()))
+                    .build(),
+            )
+            .build();
+
+        if let Err(e) = discoverable_collection.create_index(challenge_index).await {
+            error!("Failed to create index on `challenge_id`: {:?}", e);
+        }
+
+        if let Err(e) = auth_state_collection.create_index(ttl_index(ttl)).await {
+            error!("Failed to create ttl index on `created_at`: {:?}", e);
+        }
+        if let Err(e) = discoverable_collection.create_index(ttl_index(ttl)).await {
+            error!("Failed to create ttl index on `created_at`: {:?}", e);
+        }
+
+        // rows written before states were timestamped are never picked up by the ttl index
+        match auth_state_collection
+            .delete_many(doc! {"created_at": {"$exists": false}})
+            .await
+        {
+            Ok(result) if result.deleted_count > 0 => {
+                info!("Removed {} untimed auth states", result.deleted_count)
+            }
+            Ok(_) => (),
+            Err(e) => error!("Failed removing untimed auth states: {:?}", e),
+        }
+        if let Err(e) = discoverable_collection
+            .delete_many(doc! {"created_at": {"$exists": false}})
+            .await
+        {
+            error!("Failed removing untimed discoverable auth states: {:?}", e);
+        }
+
         Ok(Self {
             collection: auth_state_collection,
+            discoverable_collection,
+            ttl,
         })
     }
 
+    // the ttl monitor only runs once a minute, so expiry is also enforced on read
+    fn live_since(&self) -> bson::DateTime {
+        bson::DateTime::from_millis(
+            bson::DateTime::now().timestamp_millis() - self.ttl.as_millis() as i64,
+        )
+    }
+
     pub async fn insert(&self, auth_state_entry: AuthState) -> Result<InsertOneResult> {
         let username = &auth_state_entry.username;
-        let _auth_state_alread_exists = match self.is_exists(username).await {
+        match self.is_exists(username).await {
             Ok(data) => {
                 if data {
                     // cleanup any existing auth states
                     self.delete_by_username(username).await?;
-                } else {
-                    ()
                 }
             }
             Err(e) => {
@@ -60,12 +132,12 @@ impl AuthStateRepo {
             .collection
             .insert_one(auth_state_entry)
             .await
-            .map_err(|e| anyhow::Error::new(e));
+            .map_err(anyhow::Error::new);
         result
     }
 
     pub async fn find_by_username(&self, username: &str) -> Result<Option<AuthState>> {
-        let filter = doc! {"username": username};
+        let filter = doc! {"username": username, "created_at": {"$gt": self.live_since()}};
         let result = self.collection.find_one(filter).await.map_err(|e| {
             error!("Error finding auth state by username {}", e);
             anyhow::Error::new(e)
@@ -79,6 +151,10 @@ impl AuthStateRepo {
         Ok(self.collection.find_one(filter).await?.is_some())
     }
 
+    pub async fn is_live(&self, username: &str) -> Result<bool> {
+        Ok(self.find_by_username(username).await?.is_some())
+    }
+
     pub async fn delete_by_username(&self, username: &str) -> Result<DeleteResult> {
         let filter = doc! {"username": username};
         let result = self.collection.delete_one(filter).await.map_err(|e| {
@@ -87,4 +163,32 @@ impl AuthStateRepo {
         });
         result
     }
+
+    pub async fn insert_discoverable(
+        &self,
+        auth_state_entry: DiscoverableAuthState,
+    ) -> Result<InsertOneResult> {
+        self.discoverable_collection
+            .insert_one(auth_state_entry)
+            .await
+            .map_err(|e| {
+                error!("Error inserting discoverable auth state {}", e);
+                anyhow::Error::new(e)
+            })
+    }
+
+    // removing the state as it is read makes every challenge single use
+    pub async fn take_discoverable(
+        &self,
+        challenge_id: &str,
+    ) -> Result<Option<DiscoverableAuthState>> {
+        let filter = doc! {"challenge_id": challenge_id, "created_at": {"$gt": self.live_since()}};
+        self.discoverable_collection
+            .find_one_and_delete(filter)
+            .await
+            .map_err(|e| {
+                error!("Error taking discoverable auth state {}", e);
+                anyhow::Error::new(e)
+            })
+    }
 }
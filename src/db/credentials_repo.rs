@@ -0,0 +1,271 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures::{FutureExt, TryStreamExt};
+use log::{error, info};
+use mongodb::{
+    bson::{doc, oid::ObjectId, Document},
+    results::InsertOneResult,
+    Collection, Database, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use webauthn_rs::prelude::Passkey;
+
+use super::DB;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Credential {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub username: String,
+    // hex encoded webauthn credential id, used to find the key a login was made with
+    pub cred_id: String,
+    pub nickname: String,
+    pub passkey: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl Credential {
+    pub fn to_passkey(&self) -> Result<Passkey> {
+        Ok(serde_json::from_value(self.passkey.clone())?)
+    }
+}
+
+pub struct CredentialRepo {
+    collection: Collection<Credential>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Revocation {
+    Revoked,
+    NotFound,
+    // revoking the last passkey would lock the user out of their account
+    LastPasskey,
+}
+
+pub fn encode_cred_id(cred_id: &[u8]) -> String {
+    cred_id.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+impl CredentialRepo {
+    pub async fn init(db: &Database) -> Result<Self, Box<dyn Error>> {
+        let credentials_collection: Collection<Credential> = db.collection("credentials");
+        let indexes = vec![
+            IndexModel::builder()
+                .keys(doc! {"cred_id": 1})
+                .options(
+                    mongodb::options::IndexOptions::builder()
+                        .unique(true)
+                        .name(Some("unique_cred_id".to_string()))
+                        .build(),
+                )
+                .build(),
+            IndexModel::builder()
+                .keys(doc! {"username": 1})
+                .options(
+                    mongodb::options::IndexOptions::builder()
+                        .name(Some("username".to_string()))
+                        .build(),
+                )
+                .build(),
+        ];
+
+        if let Err(e) = credentials_collection.create_indexes(indexes).await {
+            error!("Failed to create indexes on credentials: {:?}", e);
+        }
+
+        Ok(Self {
+            collection: credentials_collection,
+        })
+    }
+
+    pub async fn insert(&self, credential: Credential) -> Result<InsertOneResult> {
+        self.collection.insert_one(credential).await.map_err(|e| {
+            error!("Error inserting credential {}", e);
+            anyhow::Error::new(e)
+        })
+    }
+
+    pub async fn find_by_username(&self, username: &str) -> Result<Vec<Credential>> {
+        let filter = doc! {"username": username};
+        let cursor = self
+            .collection
+            .find(filter)
+            .sort(doc! {"created_at": 1})
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn find_by_cred_id(&self, cred_id: &str) -> Result<Option<Credential>> {
+        let filter = doc! {"cred_id": cred_id};
+        self.collection.find_one(filter).await.map_err(|e| {
+            error!("Error finding credential {}", e);
+            anyhow::Error::new(e)
+        })
+    }
+
+    pub async fn count_by_username(&self, username: &str) -> Result<u64> {
+        let filter = doc! {"username": username};
+        Ok(self.collection.count_documents(filter).await?)
+    }
+
+    /// Stores the passkey returned by `Passkey::update_credential` (if any)
+    /// and stamps the credential as used.
+    pub async fn record_use(
+        &self,
+        cred_id: &str,
+        passkey: Option<serde_json::Value>,
+    ) -> Result<()> {
+        let mut update = doc! {"last_used_at": mongodb::bson::to_bson(&Utc::now())?};
+        if let Some(passkey) = passkey {
+            update.insert("passkey", mongodb::bson::to_bson(&passkey)?);
+        }
+        self.collection
+            .update_one(doc! {"cred_id": cred_id}, doc! {"$set": update})
+            .await?;
+        Ok(())
+    }
+
+    pub async fn rename(&self, id: ObjectId, username: &str, nickname: &str) -> Result<bool> {
+        let filter = doc! {"_id": id, "username": username};
+        let result = self
+            .collection
+            .update_one(filter, doc! {"$set": {"nickname": nickname}})
+            .await?;
+        Ok(result.matched_count > 0)
+    }
+
+    /// Deletes the credential unless it's the user's last one, in one
+    /// transaction. Concurrent revokes would each still see the other's
+    /// passkey, so both also write the user document, which makes them
+    /// conflict and the retried one count again.
+    pub async fn revoke(&self, id: ObjectId, username: &str, db: &DB) -> Result<Revocation> {
+        let mut session = db.client.start_session().await?;
+        session
+            .start_transaction()
+            .and_run(
+                (&self.collection, &db.users.collection),
+                |session, (credentials, users)| {
+                    async move {
+                        users
+                            .update_one(
+                                doc! {"username": username},
+                                doc! {"$currentDate": {"credentials_changed_at": true}},
+                            )
+                            .session(&mut *session)
+                            .await?;
+                        let count = credentials
+                            .count_documents(doc! {"username": username})
+                            .session(&mut *session)
+                            .await?;
+                        if count <= 1 {
+                            session.abort_transaction().await?;
+                            return Ok(Revocation::LastPasskey);
+                        }
+                        let result = credentials
+                            .delete_one(doc! {"_id": id, "username": username})
+                            .session(&mut *session)
+                            .await?;
+                        Ok(if result.deleted_count > 0 {
+                            Revocation::Revoked
+                        } else {
+                            Revocation::NotFound
+                        })
+                    }
+                    .boxed()
+                },
+            )
+            .await
+            .map_err(|e| {
+                error!("Error revoking credential {}", e);
+                anyhow::Error::new(e)
+            })
+    }
+
+    /// Moves the single passkey that used to live on `users.sk` into the
+    /// credentials collection. Safe to run on every start.
+    pub async fn migrate_legacy_passkeys(&self, db: &Database) -> Result<()> {
+        let users: Collection<Document> = db.collection("users");
+        let mut cursor = users.find(doc! {"sk": {"$exists": true}}).await?;
+        let mut migrated = 0;
+        while let Some(user) = cursor.try_next().await? {
+            let username = user.get_str("username")?.to_string();
+            let passkey: serde_json::Value =
+                mongodb::bson::from_bson(user.get("sk").cloned().unwrap_or_default())?;
+            let parsed: Passkey = serde_json::from_value(passkey.clone())?;
+            let cred_id = encode_cred_id(parsed.cred_id());
+            if self.find_by_cred_id(&cred_id).await?.is_none() {
+                self.insert(Credential {
+                    id: ObjectId::new(),
+                    username: username.clone(),
+                    cred_id,
+                    nickname: "Passkey 1".to_string(),
+                    passkey,
+                    created_at: Utc::now(),
+                    last_used_at: None,
+                })
+                .await?;
+            }
+            users
+                .update_one(doc! {"username": &username}, doc! {"$unset": {"sk": ""}})
+                .await?;
+            migrated += 1;
+        }
+        if migrated > 0 {
+            info!("Migrated {} legacy passkeys to credentials", migrated);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{test_db, users_repo::User};
+    use nanoid::nanoid;
+
+    // Transactions need a replica set:
+    // TEST_DB_URL=mongodb://localhost:27017/?replicaSet=rs0 cargo test -- --ignored
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    #[ignore]
+    async fn racing_revokes_keep_the_last_passkey() {
+        let db = test_db().await;
+        let username = nanoid!();
+        db.users
+            .insert(User {
+                id: None,
+                username: username.clone(),
+                uuid: nanoid!(),
+            })
+            .await
+            .unwrap();
+        let ids: Vec<ObjectId> = (0..2).map(|_| ObjectId::new()).collect();
+        for id in &ids {
+            db.credentials
+                .insert(Credential {
+                    id: *id,
+                    username: username.clone(),
+                    cred_id: id.to_hex(),
+                    nickname: "passkey".to_string(),
+                    passkey: serde_json::Value::Null,
+                    created_at: Utc::now(),
+                    last_used_at: None,
+                })
+                .await
+                .unwrap();
+        }
+
+        let (first, second) = tokio::join!(
+            db.credentials.revoke(ids[0], &username, &db),
+            db.credentials.revoke(ids[1], &username, &db)
+        );
+        let mut outcomes = [first.unwrap(), second.unwrap()];
+        outcomes.sort_by_key(|outcome| *outcome == Revocation::Revoked);
+        assert_eq!(outcomes, [Revocation::LastPasskey, Revocation::Revoked]);
+        assert_eq!(
+            db.credentials.count_by_username(&username).await.unwrap(),
+            1
+        );
+    }
+}
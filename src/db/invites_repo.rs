@@ -0,0 +1,134 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use log::error;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    options::IndexOptions,
+    Collection, Database, IndexModel,
+};
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// Access to an invite-only poll. Owners hand out one token per person, the
+/// first user to redeem it keeps it. Users who got in with the poll's access
+/// code get a tokenless invite so they don't need the code again.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Invite {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub poll_id: String,
+    // None for access granted through the access code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    // who the owner meant the invite for, only a label
+    pub invitee: Option<String>,
+    pub redeemed_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct InviteRepo {
+    pub collection: Collection<Invite>,
+}
+
+impl InviteRepo {
+    pub async fn init(db: &Database) -> Result<Self, Box<dyn Error>> {
+        let invites_collection: Collection<Invite> = db.collection("invites");
+        let indexes = vec![
+            IndexModel::builder()
+                .keys(doc! {"token": 1})
+                .options(
+                    IndexOptions::builder()
+                        .unique(true)
+                        // code grants have no token
+                        .partial_filter_expression(doc! {"token": {"$type": "string"}})
+                        .name(Some("unique_token".to_string()))
+                        .build(),
+                )
+                .build(),
+            IndexModel::builder()
+                .keys(doc! {"poll_id": 1, "redeemed_by": 1})
+                .options(
+                    IndexOptions::builder()
+                        .name(Some("poll_guests".to_string()))
+                        .build(),
+                )
+                .build(),
+        ];
+
+        if let Err(e) = invites_collection.create_indexes(indexes).await {
+            error!("Failed to create indexes on invites: {:?}", e);
+        }
+
+        Ok(Self {
+            collection: invites_collection,
+        })
+    }
+
+    pub async fn create(&self, poll_id: &str, invitee: Option<String>) -> Result<Invite> {
+        let invite = Invite {
+            id: ObjectId::new(),
+            poll_id: poll_id.to_string(),
+            token: Some(nanoid!(32)),
+            invitee,
+            redeemed_by: None,
+            created_at: Utc::now(),
+        };
+        self.collection.insert_one(&invite).await.map_err(|e| {
+            error!("Error creating invite {}", e);
+            anyhow::Error::new(e)
+        })?;
+        Ok(invite)
+    }
+
+    /// Invites handed out by the owner, code grants aren't listed.
+    pub async fn find_by_poll(&self, poll_id: &str) -> Result<Vec<Invite>> {
+        let filter = doc! {"poll_id": poll_id, "token": {"$type": "string"}};
+        Ok(self.collection.find(filter).await?.try_collect().await?)
+    }
+
+    /// Revoking also takes away the access of whoever redeemed the invite.
+    pub async fn revoke(&self, poll_id: &str, token: &str) -> Result<bool> {
+        let result = self
+            .collection
+            .delete_one(doc! {"poll_id": poll_id, "token": token})
+            .await?;
+        Ok(result.deleted_count > 0)
+    }
+
+    pub async fn has_access(&self, poll_id: &str, username: &str) -> Result<bool> {
+        let filter = doc! {"poll_id": poll_id, "redeemed_by": username};
+        Ok(self.collection.find_one(filter).await?.is_some())
+    }
+
+    /// Binds the invite to `username` on first use. Returns false when there's
+    /// no such invite or someone else already redeemed it.
+    pub async fn redeem(&self, poll_id: &str, token: &str, username: &str) -> Result<bool> {
+        let filter = doc! {
+            "poll_id": poll_id,
+            "token": token,
+            "redeemed_by": {"$in": [null, username]}
+        };
+        let update = doc! {"$set": {"redeemed_by": username}};
+        Ok(self
+            .collection
+            .find_one_and_update(filter, update)
+            .await?
+            .is_some())
+    }
+
+    /// Remembers that `username` got in with the access code.
+    pub async fn grant(&self, poll_id: &str, username: &str) -> Result<()> {
+        let invite = Invite {
+            id: ObjectId::new(),
+            poll_id: poll_id.to_string(),
+            token: None,
+            invitee: None,
+            redeemed_by: Some(username.to_string()),
+            created_at: Utc::now(),
+        };
+        self.collection.insert_one(invite).await?;
+        Ok(())
+    }
+}
@@ -1,12 +1,16 @@
 use anyhow::Result;
+use futures::TryStreamExt;
 use log::error;
 use mongodb::{
-    bson::{oid::ObjectId, Document},
+    bson::{doc, oid::ObjectId, Document},
     results::{DeleteResult, InsertOneResult},
     Collection, Database,
 };
 use serde::{Deserialize, Serialize};
-use std::error::Error;
+use std::{
+    error::Error,
+    time::{SystemTime, UNIX_EPOCH},
+};
 #[derive(Deserialize, Serialize, Debug)]
 pub struct OptionModel {
     pub _id: ObjectId,
@@ -40,4 +44,49 @@ impl OptionRepo {
         });
         result
     }
+
+    /// Options no poll refers to, created before `created_before`. Polls are
+    /// inserted after their options, so recent options may still be getting a poll.
+    pub async fn find_orphans(&self, created_before: SystemTime) -> Result<Vec<ObjectId>> {
+        // object ids start with their creation time in seconds
+        let seconds = created_before
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs() as u32);
+        let oldest_in_grace = ObjectId::from_parts(seconds, [0; 5], [0; 3]);
+        let pipeline = vec![
+            doc! {"$match": {"_id": {"$lt": oldest_in_grace}}},
+            doc! {
+                "$lookup": {
+                    "from": "polls",
+                    "localField": "_id",
+                    "foreignField": "options",
+                    "as": "polls"
+                }
+            },
+            doc! {"$match": {"polls": {"$size": 0}}},
+            doc! {"$project": {"_id": 1}},
+        ];
+        let orphans: Vec<Document> = self
+            .collection
+            .aggregate(pipeline)
+            .await?
+            .try_collect()
+            .await?;
+        Ok(orphans
+            .iter()
+            .filter_map(|orphan| orphan.get_object_id("_id").ok())
+            .collect())
+    }
+
+    pub async fn delete_many(&self, option_ids: &[ObjectId]) -> Result<u64> {
+        let result = self
+            .collection
+            .delete_many(doc! {"_id": {"$in": option_ids}})
+            .await
+            .map_err(|e| {
+                error!("Error deleting options from db {}", e);
+                anyhow::Error::new(e)
+            })?;
+        Ok(result.deleted_count)
+    }
 }
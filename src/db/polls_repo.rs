@@ -1,33 +1,583 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use futures::TryStreamExt;
+use futures::{FutureExt, TryStreamExt};
 use log::{debug, error};
 use mongodb::{
-    bson::{self, doc, oid::ObjectId, Document},
+    bson::{self, doc, oid::ObjectId, Bson, Document},
     results::InsertOneResult,
-    Collection, Database,
+    ClientSession, Collection, Database,
 };
+use nanoid::nanoid;
 use serde::{Deserialize, Serialize};
-use std::{error::Error, str};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    str,
+};
 
-use crate::models::poll_api_model::{GetPollResponse, PollOptionResult, PollResponse, PollResults};
+use crate::{
+    errors::{PollError, PollResult},
+    models::poll_api_model::{
+        GetPollResponse, PollOptionResult, PollResponse, PollResults, ReceiptProofResponse,
+        VoteLogResponse,
+    },
+    tabulation::{instant_runoff, quadratic, rating, schulze},
+};
 
-use super::DB;
+use super::{
+    invites_repo::Invite,
+    options_repo::OptionModel,
+    receipts_repo::{self, ChainHead, Receipt, ReceiptKind, ReceiptRepo},
+    secret_ballots_repo::SecretBallotRepo,
+    votes_repo::{is_duplicate_key, total_votes_stages, Ballot, Vote},
+    DB,
+};
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Poll {
     pub id: String,
     pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
     pub owner_id: String,
     pub options: Vec<ObjectId>,
     pub is_open: bool,
-    pub voters: Vec<String>,
+    #[serde(default)]
+    pub poll_type: PollType,
+    // whether voters may change or retract their vote while the poll is open
+    #[serde(default)]
+    pub allow_vote_change: bool,
+    // ballots are stored without any link to the voter, see `SecretBallotRepo`
+    #[serde(default)]
+    pub secret_ballot: bool,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub results_visibility: ResultsVisibility,
+    // lets anyone who knows it into an invite-only poll
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_code: Option<String>,
+    // voting window, stored as bson dates so they can be compared in queries
+    #[serde(
+        default,
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional"
+    )]
+    pub opens_at: Option<DateTime<Utc>>,
+    #[serde(
+        default,
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional"
+    )]
+    pub closes_at: Option<DateTime<Utc>>,
+    // set when the poll is closed, by hand or on schedule
+    #[serde(
+        default,
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional"
+    )]
+    pub closed_at: Option<DateTime<Utc>>,
+    // set while the poll sits in its owner's trash
+    #[serde(
+        default,
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional"
+    )]
+    pub deleted_at: Option<DateTime<Utc>>,
     #[serde(default = "Utc::now")]
     pub created_at: DateTime<Utc>,
     #[serde(default = "Utc::now")]
     pub updated_at: DateTime<Utc>,
+    // every edit made after creation, oldest first
+    #[serde(default)]
+    pub history: Vec<PollEdit>,
+    // last entry of the vote log, see `ReceiptRepo`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_head: Option<ChainHead>,
+    // log head and option counters sealed when the poll closed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_digest: Option<String>,
+}
+
+/// Who can find and open a poll.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    /// Listed in the public feeds.
+    #[default]
+    Public,
+    /// Anyone with the link, but never listed.
+    Unlisted,
+    /// Only the owner and people holding an invite or the access code.
+    InviteOnly,
+}
+
+/// Who can see a poll's counts and results. The owner always can.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultsVisibility {
+    /// Live counts for everyone.
+    #[default]
+    Always,
+    /// Users who voted, and everyone once the poll closes.
+    AfterVote,
+    /// Everyone, but only once the poll closes.
+    AfterClose,
+    /// Nobody but the owner, even after the poll closes.
+    OwnerOnly,
+}
+
+impl ResultsVisibility {
+    pub fn allows(self, is_owner: bool, has_voted: bool, closed: bool) -> bool {
+        is_owner
+            || match self {
+                ResultsVisibility::Always => true,
+                ResultsVisibility::AfterVote => has_voted || closed,
+                ResultsVisibility::AfterClose => closed,
+                ResultsVisibility::OwnerOnly => false,
+            }
+    }
+
+    /// Whether counts stay hidden from voters while the poll is open. Secret
+    /// ballot polls need this, otherwise watching a counter move as someone
+    /// votes gives away their choice.
+    pub fn hides_live_counts(self) -> bool {
+        matches!(
+            self,
+            ResultsVisibility::AfterClose | ResultsVisibility::OwnerOnly
+        )
+    }
+}
+
+pub const SECRET_BALLOT_RESULTS: &str =
+    "Secret ballot polls must keep results hidden until they close (after_close or owner_only)!";
+
+impl Poll {
+    /// Same as `closed_filter`, a poll past its `closes_at` is closed even
+    /// before the scheduler flips it.
+    pub fn is_closed(&self) -> bool {
+        let now = Utc::now();
+        self.closed_at.is_some()
+            || self.closes_at.is_some_and(|closes_at| closes_at <= now)
+            || (!self.is_open && self.opens_at.is_none_or(|opens_at| opens_at <= now))
+    }
+
+    /// Whether the owner sees the counts. Not while a secret ballot poll is
+    /// open, or watching them move as someone votes gives away their choice.
+    pub fn owner_sees_results(&self) -> bool {
+        !self.secret_ballot || self.is_closed()
+    }
+
+    /// Whether the title, options and counts may go out to every SSE client.
+    /// Those are anonymous and subscribe to every poll, so only public polls
+    /// whose results anyone may see qualify.
+    pub fn results_public(&self) -> bool {
+        self.visibility == Visibility::Public
+            && self
+                .results_visibility
+                .allows(false, false, self.is_closed())
+    }
+}
+
+/// One `PATCH` of a poll, kept so voters can see what changed after they voted.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PollEdit {
+    pub edited_at: DateTime<Utc>,
+    pub changes: Vec<PollChange>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PollChange {
+    Title {
+        from: String,
+        to: String,
+    },
+    Description {
+        from: Option<String>,
+        to: Option<String>,
+    },
+    Visibility {
+        from: Visibility,
+        to: Visibility,
+    },
+    ResultsVisibility {
+        from: ResultsVisibility,
+        to: ResultsVisibility,
+    },
+    OptionAdded {
+        option_id: ObjectId,
+        text: String,
+    },
+    // `discarded_votes` ballots referencing the option were thrown away with it
+    OptionRemoved {
+        option_id: ObjectId,
+        text: String,
+        discarded_votes: u64,
+    },
+}
+
+/// What an owner asked to change, every field is optional.
+#[derive(Debug, Default)]
+pub struct PollEditRequest {
+    pub title: Option<String>,
+    // Some(None) clears the description
+    pub description: Option<Option<String>>,
+    pub visibility: Option<Visibility>,
+    pub results_visibility: Option<ResultsVisibility>,
+    pub add_options: Vec<String>,
+    pub remove_options: Vec<ObjectId>,
+    // remove options even when ballots reference them, discarding those ballots
+    pub discard_votes: bool,
 }
 
+// Open and inside its voting window. The scheduler flips `is_open` at the
+// deadlines, the window is checked as well so nothing slips through between ticks.
+fn live_filter() -> Document {
+    let now = bson::DateTime::now();
+    doc! {
+        "is_open": true,
+        "deleted_at": null,
+        "$and": [
+            {"$or": [{"opens_at": null}, {"opens_at": {"$lte": now}}]},
+            {"$or": [{"closes_at": null}, {"closes_at": {"$gt": now}}]}
+        ]
+    }
+}
+
+// Scheduled to open later and not closed by hand in the meantime.
+fn upcoming_filter() -> Document {
+    doc! {
+        "is_open": false,
+        "opens_at": {"$gt": bson::DateTime::now()},
+        "closed_at": null,
+        "deleted_at": null
+    }
+}
+
+fn closed_filter() -> Document {
+    let now = bson::DateTime::now();
+    doc! {
+        "deleted_at": null,
+        "$or": [
+            {
+                "is_open": false,
+                "$or": [{"opens_at": null}, {"opens_at": {"$lte": now}}, {"closed_at": {"$ne": null}}]
+            },
+            {"is_open": true, "closes_at": {"$lte": now}}
+        ]
+    }
+}
+
+fn accepting_votes(poll_id: &str) -> Document {
+    let mut filter = live_filter();
+    filter.insert("id", poll_id);
+    filter
+}
+
+// Tells a poll that isn't accepting votes apart from one that doesn't exist.
+async fn not_accepting(
+    polls: &Collection<Poll>,
+    session: &mut ClientSession,
+    poll_id: &str,
+) -> mongodb::error::Result<PollError> {
+    let poll = polls
+        .find_one(doc! {"id": poll_id, "deleted_at": null})
+        .session(session)
+        .await?;
+    Ok(match poll {
+        Some(_) => PollError::PollClosed,
+        None => PollError::NotFound,
+    })
+}
+
+// Only public polls show up in the feeds, polls from before visibility existed are public.
+fn listed(mut filter: Document) -> Document {
+    filter.insert("visibility", doc! {"$in": [null, "public"]});
+    filter
+}
+
+// A user's polls, others only get to see the public ones.
+fn owned_filter(username: &str, include_unlisted: bool) -> Document {
+    let filter = doc! {"owner_id": username, "deleted_at": null};
+    if include_unlisted {
+        filter
+    } else {
+        listed(filter)
+    }
+}
+
+/// Counters of `poll`'s options in the poll's own option order.
+async fn option_counts(poll: &Poll, db: &DB) -> Result<Vec<(ObjectId, u64)>> {
+    let counts: HashMap<ObjectId, u64> = db
+        .options
+        .collection
+        .find(doc! {"_id": {"$in": &poll.options}})
+        .await?
+        .try_collect::<Vec<OptionModel>>()
+        .await?
+        .into_iter()
+        .map(|option| (option._id, option.votes_count))
+        .collect();
+    Ok(poll
+        .options
+        .iter()
+        .map(|option_id| (*option_id, counts.get(option_id).copied().unwrap_or(0)))
+        .collect())
+}
+
+async fn find_ballots(poll_id: &str, secret_ballot: bool, db: &DB) -> Result<Vec<Ballot>> {
+    if secret_ballot {
+        db.secret_ballots.find_ballots(poll_id).await
+    } else {
+        db.votes.find_ballots(poll_id).await
+    }
+}
+
+/// `$expr` mirroring `Poll::is_closed`.
+fn closed() -> Document {
+    let now = bson::DateTime::now();
+    doc! {
+        "$or": [
+            {"$ne": [{"$ifNull": ["$closed_at", null]}, null]},
+            {"$and": [
+                {"$ne": [{"$ifNull": ["$closes_at", null]}, null]},
+                {"$lte": ["$closes_at", now]}
+            ]},
+            {"$and": [
+                {"$eq": ["$is_open", false]},
+                {"$or": [
+                    {"$eq": [{"$ifNull": ["$opens_at", null]}, null]},
+                    {"$lte": ["$opens_at", now]}
+                ]}
+            ]}
+        ]
+    }
+}
+
+/// `$expr` telling whether anyone, voted or not, may see a poll's counts.
+/// Mirrors `ResultsVisibility::allows`.
+fn public_results() -> Bson {
+    Bson::Document(doc! {
+        "$or": [
+            {"$eq": [{"$ifNull": ["$results_visibility", "always"]}, "always"]},
+            {"$and": [
+                {"$in": ["$results_visibility", ["after_vote", "after_close"]]},
+                closed()
+            ]}
+        ]
+    })
+}
+
+/// `$expr` mirroring `Poll::owner_sees_results`.
+fn owner_results() -> Bson {
+    Bson::Document(doc! {
+        "$or": [
+            {"$ne": [{"$ifNull": ["$secret_ballot", false]}, true]},
+            closed()
+        ]
+    })
+}
+
+/// Options of a poll, leaving `votes_count` out where `show_counts` is false.
+fn listed_options(show_counts: Bson) -> Document {
+    doc! {
+        "$map": {
+            "input": "$options",
+            "as": "option",
+            "in": {
+                "_id": "$$option._id",
+                "text": "$$option.text",
+                "votes_count": {"$cond": [show_counts, "$$option.votes_count", "$$REMOVE"]},
+            }
+        }
+    }
+}
+
+fn trash_filter(username: &str) -> Document {
+    doc! {"owner_id": username, "deleted_at": {"$ne": null}}
+}
+
+/// How ballots on a poll are cast and counted.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PollType {
+    /// One option per voter.
+    #[default]
+    Single,
+    /// Any number of options per voter, within the optional bounds.
+    Approval {
+        min_selections: Option<u32>,
+        max_selections: Option<u32>,
+    },
+    /// Voters rank every option, counted by instant runoff and by Schulze.
+    Ranked,
+    /// Voters score every option between `min_score` and `max_score`.
+    Rating { min_score: i32, max_score: i32 },
+    /// Voters spread `budget` credits over the options, k votes on one option cost k² credits.
+    Quadratic { budget: u64 },
+}
+
+impl PollType {
+    /// Checks the settings make sense for a poll with `option_count` options.
+    pub fn check(&self, option_count: usize) -> Result<(), String> {
+        match self {
+            PollType::Single => Ok(()),
+            PollType::Approval {
+                min_selections,
+                max_selections,
+            } => {
+                let min = min_selections.unwrap_or(1) as usize;
+                let max = max_selections.map_or(option_count, |max| max as usize);
+                if min == 0 || max == 0 {
+                    return Err("Selections must allow at least one option!".to_string());
+                }
+                if max > option_count {
+                    return Err("Max selections exceeds the number of options!".to_string());
+                }
+                if min > max {
+                    return Err("Min selections is greater than max selections!".to_string());
+                }
+                Ok(())
+            }
+            PollType::Ranked => Ok(()),
+            PollType::Rating {
+                min_score,
+                max_score,
+            } => {
+                if min_score >= max_score {
+                    return Err("Min score must be below max score!".to_string());
+                }
+                if *min_score < -rating::MAX_SCORE || *max_score > rating::MAX_SCORE {
+                    return Err(format!(
+                        "Scores must stay between -{} and {}!",
+                        rating::MAX_SCORE,
+                        rating::MAX_SCORE
+                    ));
+                }
+                if *max_score as i64 - *min_score as i64 > rating::MAX_SCORE_SPAN {
+                    return Err(format!(
+                        "Scores can span at most {} points!",
+                        rating::MAX_SCORE_SPAN
+                    ));
+                }
+                Ok(())
+            }
+            PollType::Quadratic { budget } => {
+                if *budget == 0 || *budget > MAX_QUADRATIC_BUDGET {
+                    return Err(format!(
+                        "Budget must be between 1 and {} credits!",
+                        MAX_QUADRATIC_BUDGET
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks `ballot` can be cast on a poll of this type offering `options`.
+    pub fn validate(&self, ballot: &Ballot, options: &[ObjectId]) -> Result<(), String> {
+        match (self, ballot) {
+            (PollType::Single, Ballot::Single { option_id }) => {
+                if !options.contains(option_id) {
+                    return Err("No such option exists!".to_string());
+                }
+                Ok(())
+            }
+            (
+                PollType::Approval {
+                    min_selections,
+                    max_selections,
+                },
+                Ballot::Approval { option_ids },
+            ) => {
+                if option_ids.iter().any(|id| !options.contains(id)) {
+                    return Err("No such option exists!".to_string());
+                }
+                let unique: HashSet<&ObjectId> = option_ids.iter().collect();
+                if unique.len() != option_ids.len() {
+                    return Err("An option was selected more than once!".to_string());
+                }
+                let min = min_selections.unwrap_or(1) as usize;
+                let max = max_selections.map_or(options.len(), |max| max as usize);
+                if option_ids.len() < min || option_ids.len() > max {
+                    return Err(format!("Select between {} and {} options!", min, max));
+                }
+                Ok(())
+            }
+            (PollType::Ranked, Ballot::Ranked { rankings }) => {
+                let unique: HashSet<&ObjectId> = rankings.iter().collect();
+                if unique.len() != rankings.len() {
+                    return Err("An option was ranked more than once!".to_string());
+                }
+                if rankings.len() != options.len()
+                    || rankings.iter().any(|id| !options.contains(id))
+                {
+                    return Err("Rank every option of the poll!".to_string());
+                }
+                Ok(())
+            }
+            (
+                PollType::Rating {
+                    min_score,
+                    max_score,
+                },
+                Ballot::Rating { scores },
+            ) => {
+                let unique: HashSet<&ObjectId> = scores.iter().map(|s| &s.option_id).collect();
+                if unique.len() != scores.len() {
+                    return Err("An option was scored more than once!".to_string());
+                }
+                if scores.len() != options.len()
+                    || scores.iter().any(|s| !options.contains(&s.option_id))
+                {
+                    return Err("Score every option of the poll!".to_string());
+                }
+                if scores
+                    .iter()
+                    .any(|s| s.score < *min_score || s.score > *max_score)
+                {
+                    return Err(format!(
+                        "Scores must be between {} and {}!",
+                        min_score, max_score
+                    ));
+                }
+                Ok(())
+            }
+            (PollType::Quadratic { budget }, Ballot::Quadratic { allocations }) => {
+                if allocations.iter().any(|a| !options.contains(&a.option_id)) {
+                    return Err("No such option exists!".to_string());
+                }
+                let unique: HashSet<&ObjectId> = allocations.iter().map(|a| &a.option_id).collect();
+                if unique.len() != allocations.len() {
+                    return Err("An option was allocated more than once!".to_string());
+                }
+                if allocations.iter().all(|a| a.votes == 0) {
+                    return Err("Cast at least one vote!".to_string());
+                }
+                let max_votes = budget.isqrt();
+                if allocations.iter().any(|a| a.votes as u64 > max_votes) {
+                    return Err(format!("At most {} votes fit on one option!", max_votes));
+                }
+                let spent = quadratic::total_cost(allocations).unwrap_or(u64::MAX);
+                if spent > *budget {
+                    return Err(format!(
+                        "Ballot costs {} credits but the budget is {}!",
+                        spent, budget
+                    ));
+                }
+                Ok(())
+            }
+            _ => Err("Ballot doesn't match the poll type!".to_string()),
+        }
+    }
+}
+
+// no 0/O or 1/I/L, codes get read out loud
+const ACCESS_CODE_ALPHABET: [char; 31] = [
+    '2', '3', '4', '5', '6', '7', '8', '9', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'J', 'K', 'M',
+    'N', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z',
+];
+
+// ballots cap votes on one option at √budget, so at most 1000
+const MAX_QUADRATIC_BUDGET: u64 = 1_000_000;
+
 pub struct PollRepo {
     pub collection: Collection<Poll>,
 }
@@ -48,30 +598,292 @@ impl PollRepo {
         result
     }
 
-    pub async fn delete(&self, poll_id: &str, username: &str) -> Result<bool> {
-        // Check if the user is the owner of the poll
-        if !self.is_owner(poll_id, username).await {
-            return Ok(false); // Return false if the user is not the owner
+    /// Creates all `polls` with their options in one transaction, either every
+    /// poll ends up in the database or none does.
+    pub async fn insert_all(&self, polls: &[(Poll, Vec<OptionModel>)], db: &DB) -> Result<()> {
+        if polls.is_empty() {
+            return Ok(());
         }
+        let options: Vec<&OptionModel> = polls.iter().flat_map(|(_, options)| options).collect();
+        let polls: Vec<&Poll> = polls.iter().map(|(poll, _)| poll).collect();
+        let mut session = db.client.start_session().await?;
+        session
+            .start_transaction()
+            .and_run(
+                (&self.collection, &db.options.collection, &polls, &options),
+                |session, (collection, options_collection, polls, options)| {
+                    async move {
+                        options_collection
+                            .insert_many(options.iter().copied())
+                            .session(&mut *session)
+                            .await?;
+                        collection
+                            .insert_many(polls.iter().copied())
+                            .session(&mut *session)
+                            .await?;
+                        Ok(())
+                    }
+                    .boxed()
+                },
+            )
+            .await
+            .map_err(|e| {
+                error!("Error importing polls {}", e);
+                anyhow::Error::new(e)
+            })
+    }
 
-        // Build the query to find the poll by ID
-        let query = doc! { "id": poll_id };
+    /// Finds a poll for an owner-only operation, telling a missing poll
+    /// apart from someone else's. `trashed` picks between live and trashed polls.
+    pub async fn find_owned(
+        &self,
+        poll_id: &str,
+        username: &str,
+        trashed: bool,
+    ) -> PollResult<Poll> {
+        let filter = if trashed {
+            doc! {"id": poll_id, "deleted_at": {"$ne": null}}
+        } else {
+            doc! {"id": poll_id, "deleted_at": null}
+        };
+        match self.collection.find_one(filter).await? {
+            Some(poll) if poll.owner_id == username => Ok(poll),
+            Some(_) => Err(PollError::Forbidden),
+            None => Err(PollError::NotFound),
+        }
+    }
 
-        // Attempt to delete the poll
-        match self.collection.delete_one(query).await {
-            Ok(delete_result) => Ok(delete_result.deleted_count > 0),
-            Err(e) => {
-                error!("Error deleting poll: {:?}", e); // Log the error
-                return Err(anyhow::Error::new(e)); // Propagate the error to the caller
+    /// Finds an owned poll to export. Until a secret ballot poll closes its
+    /// results and ballots stay hidden from the owner as well.
+    pub async fn find_exportable(&self, poll_id: &str, username: &str) -> PollResult<Poll> {
+        let poll = self.find_owned(poll_id, username, false).await?;
+        if !poll.owner_sees_results() {
+            return Err(PollError::ResultsHidden);
+        }
+        Ok(poll)
+    }
+
+    /// Moves the poll to its owner's trash, where it can be restored until it's purged.
+    pub async fn delete(&self, poll_id: &str, username: &str) -> PollResult<()> {
+        self.find_owned(poll_id, username, false).await?;
+        let filter = doc! {"id": poll_id, "deleted_at": null};
+        let update = doc! {"$set": {"deleted_at": bson::DateTime::now()}};
+        if let Err(e) = self.collection.update_one(filter, update).await {
+            error!("Error deleting poll: {:?}", e);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Takes the poll back out of the trash.
+    pub async fn restore(&self, poll_id: &str, username: &str) -> PollResult<()> {
+        self.find_owned(poll_id, username, true).await?;
+        let mut filter = trash_filter(username);
+        filter.insert("id", poll_id);
+        let update = doc! {"$unset": {"deleted_at": ""}};
+        if let Err(e) = self.collection.update_one(filter, update).await {
+            error!("Error restoring poll: {:?}", e);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Permanently deletes a trashed poll.
+    pub async fn purge(&self, poll_id: &str, username: &str, db: &DB) -> PollResult<()> {
+        self.find_owned(poll_id, username, true).await?;
+        let mut filter = trash_filter(username);
+        filter.insert("id", poll_id);
+        match self.purge_one(filter, db).await? {
+            Some(_) => Ok(()),
+            // restored or purged in the meantime
+            None => Err(PollError::NotFound),
+        }
+    }
+
+    /// Lets `username` in when the poll isn't invite-only, they own it or they
+    /// already got in before. Otherwise a valid `invite` token or access `code`
+    /// is redeemed, so they don't need it next time.
+    pub async fn check_access(
+        &self,
+        poll_id: &str,
+        username: &str,
+        invite: Option<&str>,
+        code: Option<&str>,
+        db: &DB,
+    ) -> PollResult<()> {
+        let poll = match self
+            .collection
+            .find_one(doc! {"id": poll_id, "deleted_at": null})
+            .await?
+        {
+            Some(poll) => poll,
+            None => return Err(PollError::NotFound),
+        };
+        if poll.visibility != Visibility::InviteOnly || poll.owner_id == username {
+            return Ok(());
+        }
+        if db.invites.has_access(poll_id, username).await? {
+            return Ok(());
+        }
+        if let Some(token) = invite {
+            if db.invites.redeem(poll_id, token, username).await? {
+                return Ok(());
+            }
+        }
+        if let (Some(code), Some(access_code)) = (code, &poll.access_code) {
+            if code == access_code {
+                db.invites.grant(poll_id, username).await?;
+                return Ok(());
             }
         }
+        Err(PollError::InviteRequired)
+    }
+
+    /// Issues a new access code for an invite-only poll, the old one stops
+    /// working. People who already got in keep their access.
+    pub async fn rotate_access_code(&self, poll_id: &str, username: &str) -> PollResult<String> {
+        let poll = self.find_owned(poll_id, username, false).await?;
+        if poll.visibility != Visibility::InviteOnly {
+            return Err(PollError::InvalidPoll(
+                "Only invite-only polls have access codes!".to_string(),
+            ));
+        }
+        let access_code = nanoid!(8, &ACCESS_CODE_ALPHABET);
+        self.collection
+            .update_one(
+                doc! {"id": poll_id},
+                doc! {"$set": {"access_code": &access_code}},
+            )
+            .await?;
+        Ok(access_code)
+    }
+
+    pub async fn remove_access_code(&self, poll_id: &str, username: &str) -> PollResult<()> {
+        self.find_owned(poll_id, username, false).await?;
+        self.collection
+            .update_one(doc! {"id": poll_id}, doc! {"$unset": {"access_code": ""}})
+            .await?;
+        Ok(())
+    }
+
+    pub async fn create_invite(
+        &self,
+        poll_id: &str,
+        username: &str,
+        invitee: Option<String>,
+        db: &DB,
+    ) -> PollResult<Invite> {
+        let poll = self.find_owned(poll_id, username, false).await?;
+        if poll.visibility != Visibility::InviteOnly {
+            return Err(PollError::InvalidPoll(
+                "Only invite-only polls take invites!".to_string(),
+            ));
+        }
+        Ok(db.invites.create(poll_id, invitee).await?)
+    }
+
+    pub async fn list_invites(
+        &self,
+        poll_id: &str,
+        username: &str,
+        db: &DB,
+    ) -> PollResult<Vec<Invite>> {
+        self.find_owned(poll_id, username, false).await?;
+        Ok(db.invites.find_by_poll(poll_id).await?)
+    }
+
+    pub async fn revoke_invite(
+        &self,
+        poll_id: &str,
+        username: &str,
+        token: &str,
+        db: &DB,
+    ) -> PollResult<()> {
+        self.find_owned(poll_id, username, false).await?;
+        if !db.invites.revoke(poll_id, token).await? {
+            return Err(PollError::InviteNotFound);
+        }
+        Ok(())
+    }
+
+    /// Permanently deletes the next poll trashed before `deleted_before`.
+    pub async fn purge_next_expired(
+        &self,
+        deleted_before: DateTime<Utc>,
+        db: &DB,
+    ) -> Result<Option<Poll>> {
+        let filter = doc! {"deleted_at": {"$lte": bson::DateTime::from_chrono(deleted_before)}};
+        self.purge_one(filter, db).await
+    }
+
+    /// Deletes the first poll matching `filter` together with its options
+    /// and votes in one transaction.
+    async fn purge_one(&self, filter: Document, db: &DB) -> Result<Option<Poll>> {
+        let mut session = db.client.start_session().await?;
+        let result = session
+            .start_transaction()
+            .and_run(
+                (
+                    &self.collection,
+                    &db.votes.collection,
+                    &db.options.collection,
+                    &db.invites.collection,
+                    &db.secret_ballots.collection,
+                    &db.receipts.collection,
+                    &filter,
+                ),
+                |session, (polls, votes, options, invites, secret_ballots, receipts, filter)| {
+                    async move {
+                        let poll = match polls
+                            .find_one_and_delete(filter.clone())
+                            .session(&mut *session)
+                            .await?
+                        {
+                            Some(poll) => poll,
+                            None => {
+                                session.abort_transaction().await?;
+                                return Ok(None);
+                            }
+                        };
+                        options
+                            .delete_many(doc! {"_id": {"$in": &poll.options}})
+                            .session(&mut *session)
+                            .await?;
+                        votes
+                            .delete_many(doc! {"poll_id": &poll.id})
+                            .session(&mut *session)
+                            .await?;
+                        invites
+                            .delete_many(doc! {"poll_id": &poll.id})
+                            .session(&mut *session)
+                            .await?;
+                        secret_ballots
+                            .delete_many(doc! {"poll_id": &poll.id})
+                            .session(&mut *session)
+                            .await?;
+                        receipts
+                            .delete_many(doc! {"poll_id": &poll.id})
+                            .session(&mut *session)
+                            .await?;
+                        Ok(Some(poll))
+                    }
+                    .boxed()
+                },
+            )
+            .await;
+
+        result.map_err(|e| {
+            error!("Error purging poll: {:?}", e);
+            anyhow::Error::new(e)
+        })
     }
 
     pub async fn get(&self, poll_id: &str, username: &str) -> Result<PollResponse> {
-        let pipeline = vec![
+        let mut pipeline = vec![
             doc! {
                 "$match" : {
-                    "id": poll_id
+                    "id": poll_id,
+                    "deleted_at": null
                 }
             },
             doc! {
@@ -82,36 +894,82 @@ impl PollRepo {
                     "as": "options"
                 }
             },
+            // the caller's own ballot, if any
             doc! {
-                "$project": {
-                    "title": 1,
-                    "owner_id": 1,
-                    "options": 1,
-                    "is_open": 1,
-                    "created_at": 1,
-                    "updated_at": 1,
-                    "voters": 1,
-                    "id": 1,
-                    "total_votes": {"$size": "$voters"}
+                "$lookup": {
+                    "from": "votes",
+                    "let": {"poll_id": "$id"},
+                    "pipeline": [
+                        {"$match": {"$expr": {"$and": [
+                            {"$eq": ["$poll_id", "$$poll_id"]},
+                            {"$eq": ["$user_id", username]}
+                        ]}}},
+                        {"$limit": 1}
+                    ],
+                    "as": "own_vote"
                 }
             },
         ];
+        pipeline.extend(total_votes_stages());
+        pipeline.push(doc! {
+            "$set": {
+                "results_visible": {"$or": [
+                    {"$and": [{"$eq": ["$owner_id", username]}, owner_results()]},
+                    public_results(),
+                    {"$and": [
+                        {"$eq": ["$results_visibility", "after_vote"]},
+                        {"$gt": [{"$size": "$own_vote"}, 0]}
+                    ]}
+                ]}
+            }
+        });
+        pipeline.push(doc! {
+            "$project": {
+                "title": 1,
+                "description": 1,
+                "owner_id": 1,
+                "options": listed_options(Bson::String("$results_visible".to_string())),
+                "is_open": 1,
+                "poll_type": 1,
+                "allow_vote_change": 1,
+                "secret_ballot": 1,
+                "visibility": 1,
+                "results_visibility": 1,
+                "results_visible": 1,
+                "opens_at": 1,
+                "closes_at": 1,
+                "created_at": 1,
+                "updated_at": 1,
+                "history": 1,
+                "id": 1,
+                "total_votes": 1,
+                "has_voted": {"$gt": [{"$size": "$own_vote"}, 0]},
+                "own_vote": {"$arrayElemAt": ["$own_vote", 0]}
+            }
+        });
         let mut cursor = self.collection.aggregate(pipeline).await?;
 
         if let Some(doc) = cursor.try_next().await? {
+            let has_voted = username.is_empty() || doc.get_bool("has_voted")?;
+            let results_visible = doc.get_bool("results_visible")?;
+            let own_vote: Option<Vote> = match doc.get_document("own_vote") {
+                Ok(vote) => Some(bson::from_document(vote.clone())?),
+                Err(_) => None,
+            };
             // Deserialize the document into a Poll struct
             let poll: GetPollResponse = bson::from_document(doc)?;
-            let has_voted: bool;
-            if username.is_empty() {
-                has_voted = true;
-            } else {
-                // Check if the username is in the voters list
-                has_voted = poll.voters.iter().any(|voter| voter == username);
-            }
+
+            // the voter last saw the poll when casting or changing their ballot
+            let edited_since_vote = own_vote.is_some_and(|vote| {
+                let voted_at = vote.changed_at.unwrap_or(vote.created_at);
+                poll.history.iter().any(|edit| edit.edited_at > voted_at)
+            });
 
             let poll_response = PollResponse {
                 poll: Some(poll),
                 has_voted,
+                results_visible,
+                edited_since_vote,
             };
 
             Ok(poll_response)
@@ -119,6 +977,8 @@ impl PollRepo {
             Ok(PollResponse {
                 poll: None,
                 has_voted: false,
+                results_visible: false,
+                edited_since_vote: false,
             })
         }
     }
@@ -132,229 +992,723 @@ impl PollRepo {
                         return false;
                     }
                 };
-                return poll.owner_id == username;
+                poll.owner_id == username
             }
             Err(_) => false,
         }
     }
 
+    /// Records `username`'s ballot, bumps the option counters and appends it to
+    /// the poll's vote log in one transaction, returning the voter's receipt.
+    /// Racing requests for the same user conflict on the (poll_id, user_id)
+    /// index, so at most one of them ever commits, the rest get `AlreadyVoted`.
     pub async fn add_vote(
         &self,
         poll_id: &str,
         username: String,
-        option_id: ObjectId,
+        ballot: Ballot,
         db: &DB,
-    ) -> Result<bool> {
-        let mut session = db.client.start_session().await.unwrap();
-        session.start_transaction().await.unwrap();
-        // 1. Fetch poll details
-        let poll_doc = match self.get(poll_id, &username).await {
-            Ok(poll_response) => match poll_response.poll {
-                Some(poll) => poll,
-                None => {
-                    error!("Didn't get matching poll!");
-                    return Ok(false);
-                }
-            },
-            Err(e) => {
-                error!("Error fetching poll: {:?}", e);
-                return Ok(false);
-            }
-        };
+    ) -> PollResult<Receipt> {
+        let vote = Vote::new(poll_id, ballot.clone(), &username);
+        let mut session = db.client.start_session().await?;
+        let result = session
+            .start_transaction()
+            .and_run(
+                (
+                    &self.collection,
+                    &db.votes.collection,
+                    &db.options.collection,
+                    &db.secret_ballots.collection,
+                    &db.receipts.collection,
+                    &vote,
+                    &ballot,
+                ),
+                |session, (polls, votes, options, secret_ballots, receipts, vote, ballot)| {
+                    async move {
+                        // 1. The poll must be open and accept this ballot
+                        let open_poll = accepting_votes(&vote.poll_id);
+                        let poll = match polls.find_one(open_poll).session(&mut *session).await? {
+                            Some(poll) => poll,
+                            None => {
+                                debug!("Poll {} isn't accepting votes", vote.poll_id);
+                                let reason = not_accepting(polls, session, &vote.poll_id).await?;
+                                session.abort_transaction().await?;
+                                return Ok(Err(reason));
+                            }
+                        };
+                        if let Err(reason) = poll.poll_type.validate(ballot, &poll.options) {
+                            debug!("Rejected ballot on {}: {}", vote.poll_id, reason);
+                            session.abort_transaction().await?;
+                            return Ok(Err(PollError::InvalidBallot(reason)));
+                        }
 
-        // 2. Check poll status
-        let is_open = poll_doc.is_open.clone();
-        if !is_open {
-            error!("Poll closed!");
-            session.abort_transaction().await.unwrap();
-            return Ok(false); // Poll is closed
-        }
+                        // 2. Record the vote, the ledger rejects a second one from the same user.
+                        // Secret ballots only leave a trace that the user took part.
+                        if poll.secret_ballot {
+                            let participation = Vote::participation(&vote.poll_id, &vote.user_id);
+                            votes
+                                .insert_one(&participation)
+                                .session(&mut *session)
+                                .await?;
+                            SecretBallotRepo::cast(secret_ballots, &vote.poll_id, ballot, session)
+                                .await?;
+                        } else {
+                            votes.insert_one(&**vote).session(&mut *session).await?;
+                        }
 
-        // 3. Validate if option belongs to this poll
-        let poll_options = poll_doc.options;
+                        // 3. Keep the denormalized counters in step with the ledger
+                        options
+                            .update_many(
+                                doc! {"_id": {"$in": ballot.counted_options()}},
+                                doc! {"$inc": {"votes_count": 1}},
+                            )
+                            .session(&mut *session)
+                            .await?;
 
-        let option_exists = poll_options.iter().any(|option| option._id == option_id);
+                        // 4. Chain the ballot into the log
+                        let receipt = ReceiptRepo::append(
+                            receipts,
+                            polls,
+                            &poll,
+                            ReceiptKind::Cast,
+                            Some(ballot),
+                            session,
+                        )
+                        .await?;
+                        Ok(Ok(receipt))
+                    }
+                    .boxed()
+                },
+            )
+            .await;
 
-        if !option_exists {
-            error!("No such option exists!");
-            session.abort_transaction().await.unwrap();
-            return Ok(false); // Option not part of this poll
+        match result {
+            Ok(outcome) => outcome,
+            Err(e) if is_duplicate_key(&e) => {
+                debug!("{} has already voted on {}", username, poll_id);
+                Err(PollError::AlreadyVoted)
+            }
+            Err(e) => {
+                error!("Error casting vote {}", e);
+                Err(e.into())
+            }
         }
+    }
 
-        // 4. Check if user has already voted in this poll
-        let voters: Vec<String> = poll_doc.voters;
+    /// Swaps `username`'s ballot for `ballot`, moving the option counters in the same transaction.
+    pub async fn change_vote(
+        &self,
+        poll_id: &str,
+        username: &str,
+        ballot: Ballot,
+        db: &DB,
+    ) -> PollResult<Receipt> {
+        self.replace_vote(poll_id, username, Some(ballot), db).await
+    }
 
-        if voters.contains(&username) {
-            error!("Has already voted!");
-            println!("{:?} {:?}", voters, username);
-            session.abort_transaction().await.unwrap();
-            return Ok(false); // User already voted
-        }
+    /// Withdraws `username`'s vote and takes it off the option counters.
+    pub async fn retract_vote(
+        &self,
+        poll_id: &str,
+        username: &str,
+        db: &DB,
+    ) -> PollResult<Receipt> {
+        self.replace_vote(poll_id, username, None, db).await
+    }
 
-        // 5. Prepare update operations
-        let poll_filter = doc! {"id": poll_id};
-        let poll_update = doc! {
-            "$addToSet": {"voters": &username},
-        };
+    async fn replace_vote(
+        &self,
+        poll_id: &str,
+        username: &str,
+        ballot: Option<Ballot>,
+        db: &DB,
+    ) -> PollResult<Receipt> {
+        let mut session = db.client.start_session().await?;
+        let result = session
+            .start_transaction()
+            .and_run(
+                (
+                    &self.collection,
+                    &db.votes.collection,
+                    &db.options.collection,
+                    &db.receipts.collection,
+                    &ballot,
+                ),
+                |session, (polls, votes, options, receipts, ballot)| {
+                    async move {
+                        // 1. The poll must be open, allow changes and accept the new ballot
+                        let poll = match polls
+                            .find_one(accepting_votes(poll_id))
+                            .session(&mut *session)
+                            .await?
+                        {
+                            // secret ballots can't be told apart, so they can't be changed either
+                            Some(poll) if poll.allow_vote_change && !poll.secret_ballot => poll,
+                            Some(_) => {
+                                debug!("Votes on {} can't be changed", poll_id);
+                                session.abort_transaction().await?;
+                                return Ok(Err(PollError::VoteChangeNotAllowed));
+                            }
+                            None => {
+                                debug!("Poll {} isn't accepting votes", poll_id);
+                                let reason = not_accepting(polls, session, poll_id).await?;
+                                session.abort_transaction().await?;
+                                return Ok(Err(reason));
+                            }
+                        };
+                        if let Some(ballot) = ballot {
+                            if let Err(reason) = poll.poll_type.validate(ballot, &poll.options) {
+                                debug!("Rejected ballot on {}: {}", poll_id, reason);
+                                session.abort_transaction().await?;
+                                return Ok(Err(PollError::InvalidBallot(reason)));
+                            }
+                        }
 
-        let poll_update_result = self.collection.update_one(poll_filter, poll_update).await?;
-        if poll_update_result.matched_count == 0 {
-            return Ok(false); // Poll update failed
-        }
+                        // 2. Find the vote being replaced, migrated votes don't know their options
+                        let own_vote = doc! {"poll_id": poll_id, "user_id": username};
+                        let previous = votes
+                            .find_one(own_vote.clone())
+                            .session(&mut *session)
+                            .await?;
+                        let (previous, previous_ballot) = match previous
+                            .and_then(|vote| vote.ballot().map(|ballot| (vote, ballot)))
+                        {
+                            Some(found) => found,
+                            None => {
+                                debug!("{} has no changeable vote on {}", username, poll_id);
+                                session.abort_transaction().await?;
+                                return Ok(Err(PollError::NoVote));
+                            }
+                        };
+                        options
+                            .update_many(
+                                doc! {"_id": {"$in": previous_ballot.counted_options()}},
+                                doc! {"$inc": {"votes_count": -1}},
+                            )
+                            .session(&mut *session)
+                            .await?;
 
-        let option_filter = doc! {"_id": option_id};
-        let option_update = doc! {
-            "$inc": {"votes_count": 1}
-        };
+                        // 3. Store the new ballot, or drop the vote when retracting
+                        match ballot {
+                            Some(ballot) => {
+                                let changed = Vote {
+                                    id: previous.id,
+                                    created_at: previous.created_at,
+                                    changed_at: Some(Utc::now()),
+                                    metadata: previous.metadata,
+                                    ..Vote::new(poll_id, ballot.clone(), username)
+                                };
+                                votes
+                                    .replace_one(own_vote, &changed)
+                                    .session(&mut *session)
+                                    .await?;
+                                options
+                                    .update_many(
+                                        doc! {"_id": {"$in": ballot.counted_options()}},
+                                        doc! {"$inc": {"votes_count": 1}},
+                                    )
+                                    .session(&mut *session)
+                                    .await?;
+                            }
+                            None => {
+                                votes.delete_one(own_vote).session(&mut *session).await?;
+                            }
+                        }
 
-        let _option_poll_result = db
-            .options
-            .collection
-            .update_one(option_filter, option_update)
-            .await?;
-        session.commit_transaction().await.unwrap();
-        Ok(true)
+                        // 4. The earlier receipt stays in the log, this entry supersedes it
+                        let kind = match ballot {
+                            Some(_) => ReceiptKind::Change,
+                            None => ReceiptKind::Retract,
+                        };
+                        let receipt = ReceiptRepo::append(
+                            receipts,
+                            polls,
+                            &poll,
+                            kind,
+                            ballot.as_ref(),
+                            session,
+                        )
+                        .await?;
+                        Ok(Ok(receipt))
+                    }
+                    .boxed()
+                },
+            )
+            .await;
+
+        result.map_err(|e| {
+            error!("Error replacing vote {}", e);
+            PollError::from(e)
+        })?
     }
 
-    pub async fn close_poll(&self, poll_id: &str, username: &str) -> Result<bool> {
-        if !self.is_owner(poll_id, username).await {
-            return Ok(false);
-        }
+    pub async fn close_poll(&self, poll_id: &str, username: &str, db: &DB) -> PollResult<()> {
+        self.find_owned(poll_id, username, false).await?;
         let filter = doc! {"id":poll_id};
-        let result = match self
+        if let Err(e) = self
             .collection
-            .update_one(filter, doc! {"$set" : {"is_open": false}})
+            .update_one(
+                filter,
+                doc! {"$set" : {"is_open": false, "closed_at": bson::DateTime::now()}},
+            )
             .await
         {
-            Ok(_document) => true,
-            Err(e) => {
-                error!("Error closing poll {}", e);
-                return Err(anyhow::Error::new(e));
-            }
+            error!("Error closing poll {}", e);
+            return Err(e.into());
+        }
+        self.seal(poll_id, db).await?;
+        Ok(())
+    }
+
+    /// Stores the digest of a closed poll's log head and option counters, so
+    /// any later change to `votes_count` shows up in `vote_log`. Closing stops
+    /// the votes first, and only the first seal sticks.
+    pub async fn seal(&self, poll_id: &str, db: &DB) -> Result<()> {
+        let filter = doc! {"id": poll_id, "is_open": false, "final_digest": null};
+        let poll = match self.collection.find_one(filter.clone()).await? {
+            Some(poll) => poll,
+            None => return Ok(()),
         };
-        Ok(result)
+        let counts = option_counts(&poll, db).await?;
+        let digest = receipts_repo::final_digest(&poll.id, poll.chain_head.as_ref(), &counts);
+        self.collection
+            .update_one(filter, doc! {"$set": {"final_digest": digest}})
+            .await
+            .map_err(|e| {
+                error!("Error sealing poll {} {}", poll_id, e);
+                anyhow::Error::new(e)
+            })?;
+        Ok(())
     }
 
-    pub async fn reset_poll(&self, poll_id: &str, db: &DB, username: &str) -> Result<bool> {
-        if !self.is_owner(poll_id, username).await {
-            debug!("Only owner can reset the poll!");
-            return Ok(false);
-        }
-        let poll_match = match self.get(poll_id, username).await {
-            Ok(poll_response) => match poll_response.poll {
-                Some(poll) => poll,
-                None => {
-                    debug!("No matching poll to reset");
-                    return Ok(false);
-                }
-            },
-            Err(e) => {
-                error!("Error resetting poll! {:?}", e);
-                return Ok(false);
+    /// Head of the poll's vote log and, once sealed, whether the counters still match.
+    pub async fn vote_log(&self, poll_id: &str, db: &DB) -> PollResult<VoteLogResponse> {
+        let poll = self
+            .collection
+            .find_one(doc! {"id": poll_id, "deleted_at": null})
+            .await?
+            .ok_or(PollError::NotFound)?;
+        let counts_match = match &poll.final_digest {
+            Some(sealed) => {
+                let counts = option_counts(&poll, db).await?;
+                let digest =
+                    receipts_repo::final_digest(&poll.id, poll.chain_head.as_ref(), &counts);
+                Some(&digest == sealed)
             }
+            None => None,
         };
-        let options = poll_match.options;
+        Ok(VoteLogResponse {
+            poll_id: poll.id,
+            head: poll.chain_head,
+            final_digest: poll.final_digest,
+            counts_match,
+        })
+    }
 
-        for option in options {
-            let filter = doc! {"_id": option._id};
-            let update = doc! {"$set": {"votes_count": 0}};
-            db.options.collection.update_one(filter, update).await?;
-        }
+    /// The log entry with `hash` and everything appended after it.
+    pub async fn receipt_proof(
+        &self,
+        poll_id: &str,
+        hash: &str,
+        db: &DB,
+    ) -> PollResult<ReceiptProofResponse> {
+        let poll = self
+            .collection
+            .find_one(doc! {"id": poll_id, "deleted_at": null})
+            .await?
+            .ok_or(PollError::NotFound)?;
+        let entry = db
+            .receipts
+            .find_by_hash(poll_id, hash)
+            .await?
+            .ok_or(PollError::ReceiptNotFound)?;
+        let path = db.receipts.find_from(poll_id, entry.seq + 1).await?;
+        Ok(ReceiptProofResponse {
+            entry,
+            path,
+            head: poll.chain_head,
+        })
+    }
 
-        let filter = doc! {"id": poll_id};
-        let update = doc! {
-            "$set": {
-                "is_open": true,
-                "voters": Vec::<ObjectId>::new()
-            }
-        };
+    /// Clears every vote of the poll and reopens it, all in one transaction
+    /// so a vote landing halfway can't leave the counters out of step. The
+    /// vote log is append-only, so it keeps its entries and records the reset.
+    /// A sealed poll loses its digest, the next close seals the log again.
+    pub async fn reset_poll(&self, poll_id: &str, db: &DB, username: &str) -> PollResult<()> {
+        self.find_owned(poll_id, username, false).await?;
+        let mut session = db.client.start_session().await?;
+        let result = session
+            .start_transaction()
+            .and_run(
+                (
+                    &self.collection,
+                    &db.votes.collection,
+                    &db.options.collection,
+                    &db.secret_ballots.collection,
+                    &db.receipts.collection,
+                ),
+                |session, (polls, votes, options, secret_ballots, receipts)| {
+                    async move {
+                        let poll = match polls
+                            .find_one(
+                                doc! {"id": poll_id, "owner_id": username, "deleted_at": null},
+                            )
+                            .session(&mut *session)
+                            .await?
+                        {
+                            Some(poll) => poll,
+                            None => {
+                                session.abort_transaction().await?;
+                                return Ok(Err(PollError::NotFound));
+                            }
+                        };
+                        options
+                            .update_many(
+                                doc! {"_id": {"$in": &poll.options}},
+                                doc! {"$set": {"votes_count": 0}},
+                            )
+                            .session(&mut *session)
+                            .await?;
+                        votes
+                            .delete_many(doc! {"poll_id": poll_id})
+                            .session(&mut *session)
+                            .await?;
+                        secret_ballots
+                            .delete_many(doc! {"poll_id": poll_id})
+                            .session(&mut *session)
+                            .await?;
+                        if poll.chain_head.is_some() {
+                            ReceiptRepo::append(
+                                receipts,
+                                polls,
+                                &poll,
+                                ReceiptKind::Reset,
+                                None,
+                                session,
+                            )
+                            .await?;
+                        }
 
-        let result = match self.collection.update_one(filter, update).await {
-            Ok(_) => true,
-            Err(e) => {
-                error!("Error updating in reset poll {}", e);
-                return Err(anyhow::Error::new(e));
-            }
-        };
+                        // reopening hands control back to the owner, any schedule is dropped
+                        polls
+                            .update_one(
+                                doc! {"id": poll_id},
+                                doc! {
+                                    "$set": {
+                                        "is_open": true
+                                    },
+                                    "$unset": {
+                                        "opens_at": "",
+                                        "closes_at": "",
+                                        "closed_at": "",
+                                        "final_digest": ""
+                                    }
+                                },
+                            )
+                            .session(&mut *session)
+                            .await?;
+                        Ok(Ok(()))
+                    }
+                    .boxed()
+                },
+            )
+            .await;
 
-        Ok(result)
+        result.map_err(|e| {
+            error!("Error resetting poll {}", e);
+            PollError::from(e)
+        })?
     }
 
-    pub async fn get_live_polls(&self, page: u64, per_page: u64) -> Result<Vec<Document>> {
-        // Validate pagination parameters
-        let page = page.max(1);
-        let per_page = per_page.clamp(1, 10);
+    /// Applies an owner's edit in one transaction and appends it to the poll's
+    /// history. Options referenced by a ballot are only removed with
+    /// `discard_votes`, which deletes those ballots so their voters can vote again.
+    pub async fn edit_poll(
+        &self,
+        poll_id: &str,
+        username: &str,
+        edit: PollEditRequest,
+        db: &DB,
+    ) -> PollResult<()> {
+        self.find_owned(poll_id, username, false).await?;
+        let mut session = db.client.start_session().await?;
+        let result = session
+            .start_transaction()
+            .and_run(
+                (
+                    &self.collection,
+                    &db.votes.collection,
+                    &db.options.collection,
+                    &edit,
+                ),
+                |session, (polls, votes, options, edit)| {
+                    async move {
+                        // 1. Only the owner may edit
+                        let poll = match polls
+                            .find_one(
+                                doc! {"id": poll_id, "owner_id": username, "deleted_at": null},
+                            )
+                            .session(&mut *session)
+                            .await?
+                        {
+                            Some(poll) => poll,
+                            None => {
+                                session.abort_transaction().await?;
+                                return Ok(Err(PollError::NotFound));
+                            }
+                        };
 
-        // Calculate skip for pagination
-        let skip = (page - 1) * per_page;
+                        let mut changes = Vec::new();
+                        let mut set = doc! {};
+                        if let Some(title) = &edit.title {
+                            if *title != poll.title {
+                                changes.push(PollChange::Title {
+                                    from: poll.title.clone(),
+                                    to: title.clone(),
+                                });
+                                set.insert("title", title);
+                            }
+                        }
+                        if let Some(visibility) = edit.visibility {
+                            if visibility != poll.visibility {
+                                changes.push(PollChange::Visibility {
+                                    from: poll.visibility,
+                                    to: visibility,
+                                });
+                                set.insert("visibility", bson::to_bson(&visibility)?);
+                            }
+                        }
+                        if let Some(results_visibility) = edit.results_visibility {
+                            if poll.secret_ballot && !results_visibility.hides_live_counts() {
+                                session.abort_transaction().await?;
+                                return Ok(Err(PollError::InvalidPoll(
+                                    SECRET_BALLOT_RESULTS.to_string(),
+                                )));
+                            }
+                            if results_visibility != poll.results_visibility {
+                                changes.push(PollChange::ResultsVisibility {
+                                    from: poll.results_visibility,
+                                    to: results_visibility,
+                                });
+                                set.insert(
+                                    "results_visibility",
+                                    bson::to_bson(&results_visibility)?,
+                                );
+                            }
+                        }
+                        if let Some(description) = &edit.description {
+                            if *description != poll.description {
+                                changes.push(PollChange::Description {
+                                    from: poll.description.clone(),
+                                    to: description.clone(),
+                                });
+                                set.insert("description", description.clone());
+                            }
+                        }
 
-        let pipeline = vec![
-            // Match only open polls
-            doc! {
-                "$match": {
-                    "is_open": true
-                }
-            },
-            // First lookup to expand options
-            doc! {
-                "$lookup": {
-                    "from": "options",
-                    "localField": "options",
-                    "foreignField": "_id",
-                    "as": "options"
-                }
-            },
-            doc! {
-                "$addFields": {
-                    "total_votes": { "$size": "$voters" }
-                }
-            },
-            // Sort by total_voters in descending order
-            doc! {
-                "$sort": {
-                    "total_votes": -1
-                }
-            },
-            // Pagination
-            doc! {
-                "$skip": skip as i64
-            },
-            doc! {
-                "$limit": per_page as i64
-            },
-            // Final projection
-            doc! {
-                "$project": {
-                    "_id": 1,
-                    "id": 1,
-                    "title": 1,
-                    "is_open": 1,
-                    "voters": 1,
-                    "total_votes": 1,
-                    "owner_id": "$owner_id",
-                    "options": {
-                        "$map": {
-                            "input": "$options",
-                            "as": "option",
-                            "in": {
-                                "_id": "$$option._id",
-                                "text": "$$option.text",
-                                "votes_count": "$$option.votes_count",
+                        // 2. Work out the new option list and check the poll type still fits it.
+                        // Closed polls keep theirs, the sealed digest covers their counts
+                        let edits_options =
+                            !edit.add_options.is_empty() || !edit.remove_options.is_empty();
+                        if edits_options && (poll.is_closed() || poll.final_digest.is_some()) {
+                            session.abort_transaction().await?;
+                            return Ok(Err(PollError::InvalidPoll(
+                                "Options of a closed poll can't be changed!".to_string(),
+                            )));
+                        }
+                        if let Some(unknown) = edit
+                            .remove_options
+                            .iter()
+                            .find(|option_id| !poll.options.contains(option_id))
+                        {
+                            session.abort_transaction().await?;
+                            return Ok(Err(PollError::InvalidOption(format!(
+                                "Option {} isn't part of this poll!",
+                                unknown
+                            ))));
+                        }
+                        let added: Vec<OptionModel> = edit
+                            .add_options
+                            .iter()
+                            .map(|text| OptionModel {
+                                _id: ObjectId::new(),
+                                text: text.clone(),
+                                votes_count: 0,
+                            })
+                            .collect();
+                        let remaining: Vec<ObjectId> = poll
+                            .options
+                            .iter()
+                            .filter(|option_id| !edit.remove_options.contains(option_id))
+                            .copied()
+                            .chain(added.iter().map(|option| option._id))
+                            .collect();
+                        if remaining.len() < 2 {
+                            session.abort_transaction().await?;
+                            return Ok(Err(PollError::InvalidPoll(
+                                "Minimum two options are needed!".to_string(),
+                            )));
+                        }
+                        if let Err(reason) = poll.poll_type.check(remaining.len()) {
+                            session.abort_transaction().await?;
+                            return Ok(Err(PollError::InvalidPoll(reason)));
+                        }
+
+                        // 3. Removed options take the ballots referencing them along.
+                        // Secret ballots can't be picked out, so their options stay once voting started
+                        if !edit.remove_options.is_empty() && poll.secret_ballot {
+                            let voted = votes
+                                .find_one(doc! {"poll_id": poll_id})
+                                .session(&mut *session)
+                                .await?
+                                .is_some();
+                            if voted {
+                                session.abort_transaction().await?;
+                                return Ok(Err(PollError::InvalidPoll(
+                                    "Options of a secret ballot poll can't be removed once it has votes!"
+                                        .to_string(),
+                                )));
+                            }
+                        }
+                        if !edit.remove_options.is_empty() {
+                            let mut cursor = votes
+                                .find(doc! {"poll_id": poll_id})
+                                .session(&mut *session)
+                                .await?;
+                            let mut discarded = Vec::new();
+                            while let Some(vote) = cursor.next(&mut *session).await.transpose()? {
+                                if let Some(ballot) = vote.ballot() {
+                                    if let Some(option_id) = edit
+                                        .remove_options
+                                        .iter()
+                                        .find(|option_id| ballot.references(option_id))
+                                    {
+                                        if !edit.discard_votes {
+                                            session.abort_transaction().await?;
+                                            return Ok(Err(PollError::OptionHasVotes(*option_id)));
+                                        }
+                                        discarded.push((vote.id, ballot));
+                                    }
+                                }
+                            }
+                            // migrated votes never recorded their options, the counter
+                            // still holds them once the known ballots are taken off
+                            let mut removed_text = HashMap::new();
+                            let mut cursor = options
+                                .find(doc! {"_id": {"$in": &edit.remove_options}})
+                                .session(&mut *session)
+                                .await?;
+                            while let Some(option) = cursor.next(&mut *session).await.transpose()? {
+                                let known = discarded
+                                    .iter()
+                                    .filter(|(_, ballot)| {
+                                        ballot.counted_options().contains(&option._id)
+                                    })
+                                    .count() as u64;
+                                if option.votes_count > known {
+                                    session.abort_transaction().await?;
+                                    return Ok(Err(if edit.discard_votes {
+                                        PollError::InvalidPoll(format!(
+                                            "Option {} holds votes that can't be told apart, it can't be removed!",
+                                            option._id
+                                        ))
+                                    } else {
+                                        PollError::OptionHasVotes(option._id)
+                                    }));
+                                }
+                                removed_text.insert(option._id, option.text);
+                            }
+                            for option_id in &edit.remove_options {
+                                let discarded_votes = discarded
+                                    .iter()
+                                    .filter(|(_, ballot)| ballot.references(option_id))
+                                    .count()
+                                    as u64;
+                                changes.push(PollChange::OptionRemoved {
+                                    option_id: *option_id,
+                                    text: removed_text.remove(option_id).unwrap_or_default(),
+                                    discarded_votes,
+                                });
+                            }
+
+                            for (vote_id, ballot) in &discarded {
+                                options
+                                    .update_many(
+                                        doc! {"_id": {"$in": ballot.counted_options()}},
+                                        doc! {"$inc": {"votes_count": -1}},
+                                    )
+                                    .session(&mut *session)
+                                    .await?;
+                                votes
+                                    .delete_one(doc! {"_id": vote_id})
+                                    .session(&mut *session)
+                                    .await?;
                             }
+                            options
+                                .delete_many(doc! {"_id": {"$in": &edit.remove_options}})
+                                .session(&mut *session)
+                                .await?;
+                        }
+                        if !added.is_empty() {
+                            options.insert_many(&added).session(&mut *session).await?;
+                            changes.extend(added.iter().map(|option| PollChange::OptionAdded {
+                                option_id: option._id,
+                                text: option.text.clone(),
+                            }));
+                        }
+
+                        // 4. Nothing actually changed, keep the history clean
+                        if changes.is_empty() {
+                            session.abort_transaction().await?;
+                            return Ok(Ok(()));
                         }
+
+                        let now = Utc::now();
+                        set.insert("options", remaining);
+                        set.insert("updated_at", bson::to_bson(&now)?);
+                        let history_entry = PollEdit {
+                            edited_at: now,
+                            changes,
+                        };
+                        polls
+                            .update_one(
+                                doc! {"id": poll_id},
+                                doc! {
+                                    "$set": set,
+                                    "$push": {"history": bson::to_bson(&history_entry)?}
+                                },
+                            )
+                            .session(&mut *session)
+                            .await?;
+                        Ok(Ok(()))
                     }
-                }
-            },
-        ];
+                    .boxed()
+                },
+            )
+            .await;
 
-        let mut cursor = self.collection.aggregate(pipeline).await?;
-        let mut results = Vec::new();
+        result.map_err(|e| {
+            error!("Error editing poll {}", e);
+            PollError::from(e)
+        })?
+    }
 
-        while let Some(doc) = cursor.try_next().await? {
-            results.push(doc);
-        }
+    pub async fn get_live_polls(&self, page: u64, per_page: u64) -> Result<Vec<Document>> {
+        // Validate pagination parameters
+        let page = page.max(1);
+        let per_page = per_page.clamp(1, 10);
 
-        Ok(results)
+        // Calculate skip for pagination
+        let skip = (page - 1) * per_page;
+
+        // Sort by total_voters in descending order
+        self.list_polls(
+            listed(live_filter()),
+            doc! {"total_votes": -1},
+            skip,
+            per_page,
+        )
+        .await
     }
 
     pub async fn get_closed_polls(&self, page: u64, per_page: u64) -> Result<Vec<Document>> {
@@ -365,94 +1719,191 @@ impl PollRepo {
         // Calculate skip for pagination
         let skip = (page - 1) * per_page;
 
-        let pipeline = vec![
-            // Match only closed polls
-            doc! {
-                "$match": {
-                    "is_open": false
-                }
-            },
-            // First lookup to expand options
-            doc! {
-                "$lookup": {
-                    "from": "options",
-                    "localField": "options",
-                    "foreignField": "_id",
-                    "as": "options"
-                }
-            },
-            doc! {
-                "$addFields": {
-                    "total_votes": { "$size": "$voters" }
-                }
-            },
-            // Sort by total_voters in descending order
-            doc! {
-                "$sort": {
-                    "total_votes": -1
-                }
-            },
-            // Pagination
-            doc! {
-                "$skip": skip as i32
-            },
-            doc! {
-                "$limit": per_page as i32
-            },
-            // Final projection
-            doc! {
-                "$project": {
-                    "_id": 1,
-                    "id": 1,
-                    "title": 1,
-                    "voters": 1,
-                    "is_open": 1,
-                    "total_votes": 1,
-                    "owner_id": 1,
-                    "options": {
-                        "$map": {
-                            "input": "$options",
-                            "as": "option",
-                            "in": {
-                                "_id": "$$option._id",
-                                "text": "$$option.text",
-                                "votes_count": "$$option.votes_count",
-                            }
-                        }
+        self.list_polls(
+            listed(closed_filter()),
+            doc! {"total_votes": -1},
+            skip,
+            per_page,
+        )
+        .await
+    }
+
+    pub async fn get_upcoming_polls(&self, page: u64, per_page: u64) -> Result<Vec<Document>> {
+        let page = page.max(1);
+        let per_page = per_page.clamp(1, 100);
+        let skip = (page - 1) * per_page;
+
+        // Soonest to open first
+        self.list_polls(
+            listed(upcoming_filter()),
+            doc! {"opens_at": 1},
+            skip,
+            per_page,
+        )
+        .await
+    }
+
+    pub async fn get_trashed_polls(
+        &self,
+        username: &str,
+        page: u64,
+        per_page: u64,
+    ) -> Result<Vec<Document>> {
+        let page = page.max(1);
+        let per_page = per_page.clamp(1, 100);
+        let skip = (page - 1) * per_page;
+
+        // Most recently deleted first
+        self.list_polls(
+            trash_filter(username),
+            doc! {"deleted_at": -1},
+            skip,
+            per_page,
+        )
+        .await
+    }
+
+    async fn list_polls(
+        &self,
+        filter: Document,
+        sort: Document,
+        skip: u64,
+        limit: u64,
+    ) -> Result<Vec<Document>> {
+        let pipeline = [
+            vec![
+                doc! {
+                    "$match": filter
+                },
+                // First lookup to expand options
+                doc! {
+                    "$lookup": {
+                        "from": "options",
+                        "localField": "options",
+                        "foreignField": "_id",
+                        "as": "options"
                     }
-                }
-            },
-        ];
+                },
+            ],
+            total_votes_stages(),
+            vec![
+                doc! {
+                    "$sort": sort
+                },
+                // Pagination
+                doc! {
+                    "$skip": skip as i64
+                },
+                doc! {
+                    "$limit": limit as i64
+                },
+                // Final projection
+                doc! {
+                    "$project": {
+                        "_id": 1,
+                        "id": 1,
+                        "title": 1,
+                        "description": 1,
+                        "is_open": 1,
+                        "poll_type": 1,
+                        "opens_at": 1,
+                        "closes_at": 1,
+                        "deleted_at": 1,
+                        "total_votes": 1,
+                        "owner_id": 1,
+                        "results_visibility": 1,
+                        "options": listed_options(public_results())
+                    }
+                },
+            ],
+        ]
+        .concat();
 
         let mut cursor = self.collection.aggregate(pipeline).await?;
         let mut results = Vec::new();
 
-        while let Some(doc) = cursor.try_next().await? {
-            results.push(doc);
-        }
+        while let Some(doc) = cursor.try_next().await? {
+            results.push(doc);
+        }
+
+        Ok(results)
+    }
+
+    pub async fn count_live_polls(&self) -> Result<u64> {
+        self.collection
+            .count_documents(listed(live_filter()))
+            .await
+            .map_err(|e| {
+                error!("Error counting live polls! {}", e);
+                anyhow::Error::new(e)
+            })
+    }
+
+    pub async fn count_closed_polls(&self) -> Result<u64> {
+        self.collection
+            .count_documents(listed(closed_filter()))
+            .await
+            .map_err(|e| {
+                error!("Error counting closed polls! {}", e);
+                anyhow::Error::new(e)
+            })
+    }
+
+    pub async fn count_upcoming_polls(&self) -> Result<u64> {
+        self.collection
+            .count_documents(listed(upcoming_filter()))
+            .await
+            .map_err(|e| {
+                error!("Error counting upcoming polls! {}", e);
+                anyhow::Error::new(e)
+            })
+    }
 
-        Ok(results)
+    pub async fn count_trashed_polls(&self, username: &str) -> Result<u64> {
+        self.collection
+            .count_documents(trash_filter(username))
+            .await
+            .map_err(|e| {
+                error!("Error counting trashed polls! {}", e);
+                anyhow::Error::new(e)
+            })
     }
 
-    pub async fn count_live_polls(&self) -> Result<u64> {
+    /// Opens the next poll whose `opens_at` has passed and that hasn't been
+    /// opened or closed since. Returns None once there are none left.
+    pub async fn open_next_due(&self) -> Result<Option<Poll>> {
+        let now = bson::DateTime::now();
+        let filter = doc! {
+            "is_open": false,
+            "opens_at": {"$lte": now},
+            "closed_at": null,
+            "deleted_at": null,
+            "$or": [{"closes_at": null}, {"closes_at": {"$gt": now}}]
+        };
+        let update = doc! {"$set": {"is_open": true}};
         self.collection
-            .count_documents(doc! {"is_open": true})
+            .find_one_and_update(filter, update)
             .await
             .map_err(|e| {
-                error!("Error counting live polls! {}", e);
+                error!("Error opening scheduled poll {}", e);
                 anyhow::Error::new(e)
             })
     }
 
-    pub async fn count_closed_polls(&self) -> Result<u64> {
+    /// Closes the next open poll whose `closes_at` has passed.
+    pub async fn close_next_due(&self) -> Result<Option<Poll>> {
+        let now = bson::DateTime::now();
+        let filter = doc! {"is_open": true, "closes_at": {"$lte": now}, "deleted_at": null};
+        let update = doc! {"$set": {"is_open": false, "closed_at": now}};
         self.collection
-            .count_documents(doc! {"is_open": false})
+            .find_one_and_update(filter, update)
             .await
             .map_err(|e| {
-                error!("Error counting closed polls! {}", e);
+                error!("Error closing scheduled poll {}", e);
                 anyhow::Error::new(e)
             })
     }
+
     pub async fn get_polls_by_username(
         &self,
         username: &str,
@@ -460,6 +1911,7 @@ impl PollRepo {
         per_page: u64,
         sort_by: &str,
         sort_order: i8,
+        include_unlisted: bool,
     ) -> Result<Vec<Document>> {
         // Validate pagination parameters - keeping them reasonable
         let page = page.max(1);
@@ -500,56 +1952,56 @@ impl PollRepo {
         };
 
         // Create the aggregation pipeline
-        let pipeline = vec![
-            // Match polls owned by the specified username
-            doc! {
-                "$match": {
-                    "owner_id": username
-                }
-            },
-            // Lookup to expand the options
-            doc! {
-                "$lookup": {
-                    "from": "options",
-                    "localField": "options",
-                    "foreignField": "_id",
-                    "as": "options"
-                }
-            },
-            sort_doc,
-            // Apply pagination
-            doc! {
-                "$skip": skip as i64
-            },
-            doc! {
-                "$limit": per_page as i64
-            },
-            // Final projection
-            doc! {
-                "$project": {
-                    "_id": 1,
-                    "id": 1,
-                    "title": 1,
-                    "voters": 1,
-                    "is_open": 1,
-                    "created_at": 1,
-                    "updated_at": 1,
-                    "owner_id": 1,
-                    "total_votes": {"$size": "$voters"},
-                    "options": {
-                        "$map": {
-                            "input": "$options",
-                            "as": "option",
-                            "in": {
-                                "_id": "$$option._id",
-                                "text": "$$option.text",
-                                "votes_count": "$$option.votes_count",
-                            }
-                        }
+        let pipeline = [
+            vec![
+                // Match polls owned by the specified username
+                doc! {
+                    "$match": owned_filter(username, include_unlisted)
+                },
+                // Lookup to expand the options
+                doc! {
+                    "$lookup": {
+                        "from": "options",
+                        "localField": "options",
+                        "foreignField": "_id",
+                        "as": "options"
                     }
-                }
-            },
-        ];
+                },
+            ],
+            total_votes_stages(),
+            vec![
+                sort_doc,
+                // Apply pagination
+                doc! {
+                    "$skip": skip as i64
+                },
+                doc! {
+                    "$limit": per_page as i64
+                },
+                // Final projection
+                doc! {
+                    "$project": {
+                        "_id": 1,
+                        "id": 1,
+                        "title": 1,
+                        "is_open": 1,
+                        "poll_type": 1,
+                        "created_at": 1,
+                        "updated_at": 1,
+                        "owner_id": 1,
+                        "total_votes": 1,
+                        "results_visibility": 1,
+                        // owners see their own counts
+                        "options": listed_options(if include_unlisted {
+                            owner_results()
+                        } else {
+                            public_results()
+                        })
+                    }
+                },
+            ],
+        ]
+        .concat();
 
         // Execute the aggregation
         let mut cursor = self.collection.aggregate(pipeline).await?;
@@ -564,9 +2016,13 @@ impl PollRepo {
     }
 
     // Helper function to count total polls by username
-    pub async fn count_polls_by_username(&self, username: &str) -> Result<u64> {
+    pub async fn count_polls_by_username(
+        &self,
+        username: &str,
+        include_unlisted: bool,
+    ) -> Result<u64> {
         self.collection
-            .count_documents(doc! {"owner_id": username})
+            .count_documents(owned_filter(username, include_unlisted))
             .await
             .map_err(|e| {
                 error!("Error counting user polls! {}", e);
@@ -574,13 +2030,52 @@ impl PollRepo {
             })
     }
 
-    pub async fn get_poll_results(&self, poll_id: &str) -> Result<Option<PollResults>> {
+    /// Results as `username` may see them under the poll's `results_visibility`.
+    pub async fn get_visible_results(
+        &self,
+        poll_id: &str,
+        username: &str,
+        db: &DB,
+    ) -> PollResult<Option<PollResults>> {
+        let poll = self
+            .collection
+            .find_one(doc! {"id": poll_id, "deleted_at": null})
+            .await?
+            .ok_or(PollError::NotFound)?;
+        let has_voted = poll.results_visibility == ResultsVisibility::AfterVote
+            && db.votes.has_voted(poll_id, username).await?;
+        let is_owner = poll.owner_id == username && poll.owner_sees_results();
+        if !poll
+            .results_visibility
+            .allows(is_owner, has_voted, poll.is_closed())
+        {
+            return Err(PollError::ResultsHidden);
+        }
+        Ok(self.get_poll_results(poll_id, db).await?)
+    }
+
+    pub async fn find(&self, poll_id: &str) -> Result<Option<Poll>> {
+        Ok(self
+            .collection
+            .find_one(doc! {"id": poll_id, "deleted_at": null})
+            .await?)
+    }
+
+    pub async fn get_poll_results(&self, poll_id: &str, db: &DB) -> Result<Option<PollResults>> {
         // Create an aggregation pipeline to get poll details with options
-        let pipeline = vec![
+        let pipeline = [
+            vec![
             // Match the specific poll
             doc! {
                 "$match": {
-                    "id": poll_id
+                    "id": poll_id,
+                    "deleted_at": null
+                }
+            },
+            // $lookup doesn't keep the order options were listed in
+            doc! {
+                "$addFields": {
+                    "option_order": "$options"
                 }
             },
             // Lookup to get the options
@@ -592,15 +2087,9 @@ impl PollRepo {
                     "as": "options"
                 }
             },
-            doc! {
-                "$addFields": {
-                    "total_votes": {
-                        "$toLong" : {
-                            "$sum": "$options.votes_count"
-                        }
-                    }
-                }
-            },
+            ],
+            total_votes_stages(),
+            vec![
             // Project the final format
             doc! {
                 "$project": {
@@ -608,11 +2097,15 @@ impl PollRepo {
                     "id": 1,
                     "total_votes": 1,
                     "title": 1,
+                    "poll_type": 1,
+                    "secret_ballot": 1,
+                    "option_order": 1,
                     "options": {
                         "$map": {
                             "input": "$options",
                             "as": "option",
                             "in": {
+                                "_id": "$$option._id",
                                 "text": "$$option.text",
                                 "votes_count": { "$toLong": "$$option.votes_count" },
                                 "votes_percentage": {
@@ -632,18 +2125,30 @@ impl PollRepo {
                     }
                 }
             },
-        ];
+            ],
+        ]
+        .concat();
 
         // Execute the aggregation pipeline
         let mut cursor = self.collection.aggregate(pipeline).await?;
 
         // Get the first (and should be only) result
         if let Some(doc) = cursor.try_next().await? {
-            println!("{}", doc);
             // Convert BSON document to our PollResults structure
             let id = doc.get_str("id")?.to_string();
             let title = doc.get_str("title")?.to_string();
             let total_votes = doc.get_i64("total_votes")?;
+            let secret_ballot = doc.get_bool("secret_ballot").unwrap_or(false);
+            let poll_type: PollType = match doc.get_document("poll_type") {
+                Ok(poll_type) => bson::from_document(poll_type.clone())?,
+                Err(_) => PollType::default(),
+            };
+
+            let option_order: Vec<ObjectId> = doc
+                .get_array("option_order")?
+                .iter()
+                .filter_map(|id| id.as_object_id())
+                .collect();
 
             let options_array = doc.get_array("options")?;
             let mut options = Vec::new();
@@ -651,21 +2156,956 @@ impl PollRepo {
             for option_doc in options_array {
                 if let bson::Bson::Document(option) = option_doc {
                     options.push(PollOptionResult {
+                        id: option.get_object_id("_id")?,
                         text: option.get_str("text")?.to_string(),
                         votes_count: option.get_i64("votes_count")?,
                         votes_percentage: option.get_f64("votes_percentage")?,
+                        rating: None,
+                        quadratic: None,
                     });
                 }
             }
+            options.sort_by_key(|option| option_order.iter().position(|id| *id == option.id));
+
+            let mut runoff = None;
+            let mut schulze = None;
+            match poll_type {
+                PollType::Ranked => {
+                    let ballots: Vec<Vec<ObjectId>> = find_ballots(&id, secret_ballot, db)
+                        .await?
+                        .into_iter()
+                        .filter_map(|ballot| match ballot {
+                            Ballot::Ranked { rankings } => Some(rankings),
+                            _ => None,
+                        })
+                        .collect();
+                    runoff = Some(instant_runoff::tabulate(&option_order, &ballots));
+                    schulze = Some(schulze::tabulate(&option_order, &ballots));
+                }
+                PollType::Rating {
+                    min_score,
+                    max_score,
+                } => {
+                    let mut scores: HashMap<ObjectId, Vec<i32>> = HashMap::new();
+                    for ballot in find_ballots(&id, secret_ballot, db).await? {
+                        if let Ballot::Rating {
+                            scores: ballot_scores,
+                        } = ballot
+                        {
+                            for option_score in ballot_scores {
+                                scores
+                                    .entry(option_score.option_id)
+                                    .or_default()
+                                    .push(option_score.score);
+                            }
+                        }
+                    }
+                    for option in options.iter_mut() {
+                        let option_scores = scores.get(&option.id).map_or(&[][..], Vec::as_slice);
+                        option.rating =
+                            Some(rating::summarize(option_scores, min_score, max_score));
+                    }
+                }
+                PollType::Quadratic { .. } => {
+                    let ballots: Vec<Vec<quadratic::Allocation>> =
+                        find_ballots(&id, secret_ballot, db)
+                            .await?
+                            .into_iter()
+                            .filter_map(|ballot| match ballot {
+                                Ballot::Quadratic { allocations } => Some(allocations),
+                                _ => None,
+                            })
+                            .collect();
+                    let mut tallies = quadratic::tally(&ballots);
+                    for option in options.iter_mut() {
+                        option.quadratic = Some(tallies.remove(&option.id).unwrap_or_default());
+                    }
+                }
+                _ => (),
+            }
 
             Ok(Some(PollResults {
                 id,
                 title,
+                poll_type,
                 options,
                 total_votes,
+                runoff,
+                schulze,
             }))
         } else {
             Ok(None)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        db::{options_repo::OptionModel, test_db, votes_repo::OptionScore},
+        models::poll_api_model::{NewPollRequest, OptionRequest},
+        tabulation::quadratic::Allocation,
+    };
+    use futures::future::join_all;
+    use nanoid::nanoid;
+    use std::sync::Arc;
+
+    #[test]
+    fn ballots_reference_the_options_they_depend_on() {
+        let options: Vec<ObjectId> = (0..3).map(|_| ObjectId::new()).collect();
+        let ranked = Ballot::Ranked {
+            rankings: options.clone(),
+        };
+        let quadratic = Ballot::Quadratic {
+            allocations: vec![
+                Allocation {
+                    option_id: options[0],
+                    votes: 2,
+                },
+                Allocation {
+                    option_id: options[1],
+                    votes: 0,
+                },
+            ],
+        };
+
+        assert!(options.iter().all(|option_id| ranked.references(option_id)));
+        assert!(quadratic.references(&options[0]));
+        // a zero allocation is as good as leaving the option out
+        assert!(!quadratic.references(&options[1]));
+        assert!(!quadratic.references(&options[2]));
+    }
+
+    #[test]
+    fn approval_ballots_respect_selection_bounds() {
+        let options: Vec<ObjectId> = (0..4).map(|_| ObjectId::new()).collect();
+        let poll_type = PollType::Approval {
+            min_selections: Some(2),
+            max_selections: Some(3),
+        };
+        let approve = |picked: &[ObjectId]| Ballot::Approval {
+            option_ids: picked.to_vec(),
+        };
+
+        assert!(poll_type
+            .validate(&approve(&options[..2]), &options)
+            .is_ok());
+        assert!(poll_type
+            .validate(&approve(&options[..3]), &options)
+            .is_ok());
+        assert!(poll_type
+            .validate(&approve(&options[..1]), &options)
+            .is_err());
+        assert!(poll_type.validate(&approve(&options), &options).is_err());
+        assert!(poll_type
+            .validate(&approve(&[options[0], options[0]]), &options)
+            .is_err());
+        assert!(poll_type
+            .validate(&approve(&[options[0], ObjectId::new()]), &options)
+            .is_err());
+        assert!(poll_type
+            .validate(
+                &Ballot::Single {
+                    option_id: options[0]
+                },
+                &options
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn approval_settings_must_fit_the_options() {
+        let approval = |min_selections, max_selections| PollType::Approval {
+            min_selections,
+            max_selections,
+        };
+        assert!(approval(None, None).check(3).is_ok());
+        assert!(approval(Some(2), Some(2)).check(3).is_ok());
+        assert!(approval(None, Some(4)).check(3).is_err());
+        assert!(approval(Some(3), Some(2)).check(3).is_err());
+        assert!(approval(Some(0), None).check(3).is_err());
+    }
+
+    #[test]
+    fn ranked_ballots_must_order_every_option_once() {
+        let options: Vec<ObjectId> = (0..3).map(|_| ObjectId::new()).collect();
+        let rank = |order: &[ObjectId]| Ballot::Ranked {
+            rankings: order.to_vec(),
+        };
+
+        assert!(PollType::Ranked
+            .validate(&rank(&[options[2], options[0], options[1]]), &options)
+            .is_ok());
+        assert!(PollType::Ranked
+            .validate(&rank(&options[..2]), &options)
+            .is_err());
+        assert!(PollType::Ranked
+            .validate(&rank(&[options[0], options[0], options[1]]), &options)
+            .is_err());
+    }
+
+    #[test]
+    fn rating_ballots_score_every_option_in_range() {
+        let options: Vec<ObjectId> = (0..2).map(|_| ObjectId::new()).collect();
+        let poll_type = PollType::Rating {
+            min_score: 1,
+            max_score: 5,
+        };
+        let rate = |first: i32, second: i32| Ballot::Rating {
+            scores: vec![
+                OptionScore {
+                    option_id: options[0],
+                    score: first,
+                },
+                OptionScore {
+                    option_id: options[1],
+                    score: second,
+                },
+            ],
+        };
+
+        assert!(poll_type.validate(&rate(1, 5), &options).is_ok());
+        assert!(poll_type.validate(&rate(0, 5), &options).is_err());
+        assert!(poll_type.validate(&rate(3, 6), &options).is_err());
+        assert!(PollType::Rating {
+            min_score: 5,
+            max_score: 5
+        }
+        .check(2)
+        .is_err());
+    }
+
+    #[test]
+    fn secret_ballots_need_hidden_results() {
+        let request = |results_visibility| NewPollRequest {
+            title: "secret".to_string(),
+            options: vec![
+                OptionRequest {
+                    text: "yes".to_string(),
+                },
+                OptionRequest {
+                    text: "no".to_string(),
+                },
+            ],
+            secret_ballot: true,
+            results_visibility,
+            ..Default::default()
+        };
+        assert_eq!(
+            request(ResultsVisibility::Always)
+                .into_poll("owner")
+                .err()
+                .as_deref(),
+            Some(SECRET_BALLOT_RESULTS)
+        );
+        assert!(request(ResultsVisibility::AfterVote)
+            .into_poll("owner")
+            .is_err());
+        assert!(request(ResultsVisibility::AfterClose)
+            .into_poll("owner")
+            .is_ok());
+        assert!(request(ResultsVisibility::OwnerOnly)
+            .into_poll("owner")
+            .is_ok());
+    }
+
+    #[test]
+    fn rating_ranges_are_bounded() {
+        let rating = |min_score, max_score| PollType::Rating {
+            min_score,
+            max_score,
+        };
+        assert!(rating(-50, 50).check(2).is_ok());
+        assert!(rating(0, 101).check(2).is_err());
+        assert!(rating(i32::MIN, i32::MAX).check(2).is_err());
+        assert!(rating(i32::MAX - 10, i32::MAX).check(2).is_err());
+    }
+
+    #[test]
+    fn quadratic_ballots_stay_within_budget() {
+        let options: Vec<ObjectId> = (0..2).map(|_| ObjectId::new()).collect();
+        let poll_type = PollType::Quadratic { budget: 10 };
+        let allocate = |first: u32, second: u32| Ballot::Quadratic {
+            allocations: vec![
+                Allocation {
+                    option_id: options[0],
+                    votes: first,
+                },
+                Allocation {
+                    option_id: options[1],
+                    votes: second,
+                },
+            ],
+        };
+
+        assert!(poll_type.validate(&allocate(3, 1), &options).is_ok());
+        assert!(poll_type.validate(&allocate(3, 2), &options).is_err());
+        assert!(poll_type.validate(&allocate(0, 0), &options).is_err());
+        assert!(poll_type.validate(&allocate(4, 0), &options).is_err());
+        assert!(poll_type
+            .validate(&allocate(u32::MAX, u32::MAX), &options)
+            .is_err());
+        assert!(PollType::Quadratic { budget: 0 }.check(2).is_err());
+    }
+
+    // Transactions need a replica set:
+    // TEST_DB_URL=mongodb://localhost:27017/?replicaSet=rs0 cargo test -- --ignored
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    #[ignore]
+    async fn parallel_votes_from_one_user_are_counted_once() {
+        const ATTEMPTS: usize = 300;
+        let db = Arc::new(test_db().await);
+
+        let option_id = ObjectId::new();
+        db.options
+            .insert(OptionModel {
+                _id: option_id,
+                text: "yes".to_string(),
+                votes_count: 0,
+            })
+            .await
+            .unwrap();
+        let poll_id = nanoid!();
+        db.polls
+            .insert(Poll {
+                title: "race".to_string(),
+                ..test_poll(&poll_id, vec![option_id])
+            })
+            .await
+            .unwrap();
+
+        let results = join_all((0..ATTEMPTS).map(|_| {
+            let db = db.clone();
+            let poll_id = poll_id.clone();
+            tokio::spawn(async move {
+                db.polls
+                    .add_vote(
+                        &poll_id,
+                        "racer".to_string(),
+                        Ballot::Single { option_id },
+                        &db,
+                    )
+                    .await
+            })
+        }))
+        .await;
+
+        let mut accepted = 0;
+        for result in results {
+            match result.unwrap() {
+                Ok(_) => accepted += 1,
+                Err(PollError::AlreadyVoted) => (),
+                Err(e) => panic!("unexpected rejection {:?}", e),
+            }
+        }
+        assert_eq!(accepted, 1);
+
+        let option = db
+            .options
+            .collection
+            .find_one(doc! {"_id": option_id})
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(option.votes_count, 1);
+        let ballots = db
+            .votes
+            .collection
+            .count_documents(doc! {"poll_id": &poll_id})
+            .await
+            .unwrap();
+        assert_eq!(ballots, 1);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn purging_a_trashed_poll_removes_its_options_and_votes() {
+        let db = test_db().await;
+
+        let option_ids = vec![ObjectId::new(), ObjectId::new()];
+        for option_id in &option_ids {
+            db.options
+                .insert(OptionModel {
+                    _id: *option_id,
+                    text: option_id.to_hex(),
+                    votes_count: 0,
+                })
+                .await
+                .unwrap();
+        }
+        let poll_id = nanoid!();
+        db.polls
+            .insert(Poll {
+                title: "cascade".to_string(),
+                ..test_poll(&poll_id, option_ids.clone())
+            })
+            .await
+            .unwrap();
+        let ballot = Ballot::Single {
+            option_id: option_ids[0],
+        };
+        db.polls
+            .add_vote(&poll_id, "voter".to_string(), ballot, &db)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            db.polls.delete(&poll_id, "someone").await,
+            Err(PollError::Forbidden)
+        ));
+        db.polls.delete(&poll_id, "owner").await.unwrap();
+        assert!(db
+            .polls
+            .get(&poll_id, "owner")
+            .await
+            .unwrap()
+            .poll
+            .is_none());
+        // trashed polls can be restored and trashed again
+        db.polls.restore(&poll_id, "owner").await.unwrap();
+        assert!(db
+            .polls
+            .get(&poll_id, "owner")
+            .await
+            .unwrap()
+            .poll
+            .is_some());
+        db.polls.delete(&poll_id, "owner").await.unwrap();
+
+        assert!(matches!(
+            db.polls.purge(&poll_id, "someone", &db).await,
+            Err(PollError::Forbidden)
+        ));
+        db.polls.purge(&poll_id, "owner", &db).await.unwrap();
+        assert!(matches!(
+            db.polls.purge(&poll_id, "owner", &db).await,
+            Err(PollError::NotFound)
+        ));
+
+        let options = db
+            .options
+            .collection
+            .count_documents(doc! {"_id": {"$in": &option_ids}})
+            .await
+            .unwrap();
+        assert_eq!(options, 0);
+        let votes = db
+            .votes
+            .collection
+            .count_documents(doc! {"poll_id": &poll_id})
+            .await
+            .unwrap();
+        assert_eq!(votes, 0);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn options_holding_migrated_votes_arent_removed() {
+        let db = test_db().await;
+        let options: Vec<ObjectId> = (0..3).map(|_| ObjectId::new()).collect();
+        for (option_id, votes_count) in options.iter().zip([1, 0, 0]) {
+            db.options
+                .insert(OptionModel {
+                    _id: *option_id,
+                    text: "option".to_string(),
+                    votes_count,
+                })
+                .await
+                .unwrap();
+        }
+        let poll_id = nanoid!();
+        db.polls
+            .insert(test_poll(&poll_id, options.clone()))
+            .await
+            .unwrap();
+        // what `migrate_legacy_voters` leaves behind, counted but without a ballot
+        db.votes
+            .insert(&Vote {
+                metadata: doc! {"migrated_from": "poll_voters"},
+                ..Vote::participation(&poll_id, "legacy")
+            })
+            .await
+            .unwrap();
+
+        let remove = |option_id: ObjectId, discard_votes: bool| PollEditRequest {
+            remove_options: vec![option_id],
+            discard_votes,
+            ..Default::default()
+        };
+        assert!(matches!(
+            db.polls
+                .edit_poll(&poll_id, "owner", remove(options[0], false), &db)
+                .await,
+            Err(PollError::OptionHasVotes(option_id)) if option_id == options[0]
+        ));
+        assert!(matches!(
+            db.polls
+                .edit_poll(&poll_id, "owner", remove(options[0], true), &db)
+                .await,
+            Err(PollError::InvalidPoll(_))
+        ));
+        db.polls
+            .edit_poll(&poll_id, "owner", remove(options[2], false), &db)
+            .await
+            .unwrap();
+
+        let option = db
+            .options
+            .collection
+            .find_one(doc! {"_id": options[0]})
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(option.votes_count, 1);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn closed_polls_keep_their_options() {
+        let db = test_db().await;
+        let options: Vec<ObjectId> = (0..3).map(|_| ObjectId::new()).collect();
+        let poll_id = nanoid!();
+        db.polls
+            .insert(Poll {
+                closed_at: Some(Utc::now()),
+                ..test_poll(&poll_id, options.clone())
+            })
+            .await
+            .unwrap();
+
+        for edit in [
+            PollEditRequest {
+                add_options: vec!["late".to_string()],
+                ..Default::default()
+            },
+            PollEditRequest {
+                remove_options: vec![options[2]],
+                ..Default::default()
+            },
+        ] {
+            assert!(matches!(
+                db.polls.edit_poll(&poll_id, "owner", edit, &db).await,
+                Err(PollError::InvalidPoll(_))
+            ));
+        }
+        db.polls
+            .edit_poll(
+                &poll_id,
+                "owner",
+                PollEditRequest {
+                    title: Some("renamed".to_string()),
+                    ..Default::default()
+                },
+                &db,
+            )
+            .await
+            .unwrap();
+        let poll = db.polls.find(&poll_id).await.unwrap().unwrap();
+        assert_eq!(poll.options, options);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn owners_dont_watch_open_secret_ballots() {
+        let db = test_db().await;
+        let options: Vec<ObjectId> = (0..2).map(|_| ObjectId::new()).collect();
+        for option_id in &options {
+            db.options
+                .insert(OptionModel {
+                    _id: *option_id,
+                    text: "option".to_string(),
+                    votes_count: 0,
+                })
+                .await
+                .unwrap();
+        }
+        let poll_id = nanoid!();
+        db.polls
+            .insert(Poll {
+                secret_ballot: true,
+                results_visibility: ResultsVisibility::OwnerOnly,
+                ..test_poll(&poll_id, options.clone())
+            })
+            .await
+            .unwrap();
+        db.polls
+            .add_vote(
+                &poll_id,
+                "voter".to_string(),
+                Ballot::Single {
+                    option_id: options[0],
+                },
+                &db,
+            )
+            .await
+            .unwrap();
+
+        let view = db.polls.get(&poll_id, "owner").await.unwrap();
+        assert!(!view.results_visible);
+        assert!(view
+            .poll
+            .unwrap()
+            .options
+            .iter()
+            .all(|option| option.votes_count.is_none()));
+        assert!(matches!(
+            db.polls.get_visible_results(&poll_id, "owner", &db).await,
+            Err(PollError::ResultsHidden)
+        ));
+
+        assert!(matches!(
+            db.polls.find_exportable(&poll_id, "owner").await,
+            Err(PollError::ResultsHidden)
+        ));
+
+        db.polls.close_poll(&poll_id, "owner", &db).await.unwrap();
+        db.polls.find_exportable(&poll_id, "owner").await.unwrap();
+        let view = db.polls.get(&poll_id, "owner").await.unwrap();
+        assert!(view.results_visible);
+        assert!(view
+            .poll
+            .unwrap()
+            .options
+            .iter()
+            .any(|option| option.votes_count == Some(1)));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn invite_only_polls_need_an_invite_or_the_code() {
+        let db = test_db().await;
+        let poll_id = nanoid!();
+        db.polls
+            .insert(Poll {
+                visibility: Visibility::InviteOnly,
+                access_code: Some("ABCD2345".to_string()),
+                ..test_poll(&poll_id, vec![ObjectId::new(), ObjectId::new()])
+            })
+            .await
+            .unwrap();
+        let access =
+            |username: &'static str, invite: Option<String>, code: Option<&'static str>| {
+                let db = &db;
+                let poll_id = poll_id.clone();
+                async move {
+                    db.polls
+                        .check_access(&poll_id, username, invite.as_deref(), code, db)
+                        .await
+                }
+            };
+
+        access("owner", None, None).await.unwrap();
+        assert!(matches!(
+            access("guest", None, Some("WRONG")).await,
+            Err(PollError::InviteRequired)
+        ));
+        access("guest", None, Some("ABCD2345")).await.unwrap();
+        // remembered without the code
+        access("guest", None, None).await.unwrap();
+
+        let invite = db
+            .polls
+            .create_invite(&poll_id, "owner", Some("friend".to_string()), &db)
+            .await
+            .unwrap();
+        access("friend", invite.token.clone(), None).await.unwrap();
+        // an invite only works for whoever redeemed it first
+        assert!(matches!(
+            access("stranger", invite.token.clone(), None).await,
+            Err(PollError::InviteRequired)
+        ));
+
+        db.polls
+            .revoke_invite(&poll_id, "owner", invite.token.as_deref().unwrap(), &db)
+            .await
+            .unwrap();
+        assert!(matches!(
+            access("friend", None, None).await,
+            Err(PollError::InviteRequired)
+        ));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn secret_ballots_cant_be_traced_back_to_voters() {
+        let db = test_db().await;
+        let options: Vec<ObjectId> = (0..2).map(|_| ObjectId::new()).collect();
+        for option_id in &options {
+            db.options
+                .insert(OptionModel {
+                    _id: *option_id,
+                    text: "option".to_string(),
+                    votes_count: 0,
+                })
+                .await
+                .unwrap();
+        }
+        let poll_id = nanoid!();
+        db.polls
+            .insert(Poll {
+                secret_ballot: true,
+                results_visibility: ResultsVisibility::AfterClose,
+                ..test_poll(&poll_id, options.clone())
+            })
+            .await
+            .unwrap();
+
+        for (username, option_id) in [("alice", options[0]), ("bob", options[1])] {
+            db.polls
+                .add_vote(
+                    &poll_id,
+                    username.to_string(),
+                    Ballot::Single { option_id },
+                    &db,
+                )
+                .await
+                .unwrap();
+        }
+        assert!(matches!(
+            db.polls
+                .add_vote(
+                    &poll_id,
+                    "alice".to_string(),
+                    Ballot::Single {
+                        option_id: options[1]
+                    },
+                    &db
+                )
+                .await,
+            Err(PollError::AlreadyVoted)
+        ));
+        assert!(matches!(
+            db.polls
+                .change_vote(
+                    &poll_id,
+                    "alice",
+                    Ballot::Single {
+                        option_id: options[1]
+                    },
+                    &db
+                )
+                .await,
+            Err(PollError::VoteChangeNotAllowed)
+        ));
+
+        // the ledger only knows who took part
+        let votes: Vec<Vote> = db
+            .votes
+            .collection
+            .find(doc! {"poll_id": &poll_id})
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(votes.len(), 2);
+        assert!(votes.iter().all(|vote| vote.ballot().is_none()));
+
+        // and the ballots don't know who cast them or when
+        let buckets: Vec<Document> = db
+            .secret_ballots
+            .collection
+            .clone_with_type::<Document>()
+            .find(doc! {"poll_id": &poll_id})
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        for bucket in &buckets {
+            let keys: Vec<&str> = bucket.keys().map(String::as_str).collect();
+            assert_eq!(keys, ["_id", "poll_id", "ballots"]);
+            for sealed in bucket.get_array("ballots").unwrap() {
+                let keys: Vec<&str> = sealed
+                    .as_document()
+                    .unwrap()
+                    .keys()
+                    .map(String::as_str)
+                    .collect();
+                assert_eq!(keys, ["k", "ballot"]);
+            }
+            let raw = bucket.to_string();
+            assert!(!raw.contains("alice") && !raw.contains("bob"));
+        }
+
+        let results = db
+            .polls
+            .get_poll_results(&poll_id, &db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(results.total_votes, 2);
+        assert_eq!(
+            db.secret_ballots
+                .find_ballots(&poll_id)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn receipts_chain_up_to_the_sealed_digest() {
+        let db = test_db().await;
+        let options: Vec<ObjectId> = (0..2).map(|_| ObjectId::new()).collect();
+        for option_id in &options {
+            db.options
+                .insert(OptionModel {
+                    _id: *option_id,
+                    text: "option".to_string(),
+                    votes_count: 0,
+                })
+                .await
+                .unwrap();
+        }
+        let poll_id = nanoid!();
+        db.polls
+            .insert(test_poll(&poll_id, options.clone()))
+            .await
+            .unwrap();
+
+        let mut receipts = Vec::new();
+        for (username, option_id) in [("alice", options[0]), ("bob", options[1])] {
+            let ballot = Ballot::Single { option_id };
+            let receipt = db
+                .polls
+                .add_vote(&poll_id, username.to_string(), ballot.clone(), &db)
+                .await
+                .unwrap();
+            // the voter can tie the receipt to their own ballot
+            assert_eq!(
+                receipts_repo::commitment(&poll_id, &receipt.nonce, Some(&ballot)),
+                receipt.commitment
+            );
+            receipts.push(receipt);
+        }
+
+        let proof = db
+            .polls
+            .receipt_proof(&poll_id, &receipts[0].hash, &db)
+            .await
+            .unwrap();
+        let mut path = vec![proof.entry];
+        path.extend(proof.path);
+        assert_eq!(
+            receipts_repo::verify_path(&path),
+            proof.head.map(|head| head.hash)
+        );
+
+        db.polls.close_poll(&poll_id, "owner", &db).await.unwrap();
+        let vote_log = db.polls.vote_log(&poll_id, &db).await.unwrap();
+        assert!(vote_log.final_digest.is_some());
+        assert_eq!(vote_log.counts_match, Some(true));
+
+        // new options would change the sealed counts
+        let add = PollEditRequest {
+            add_options: vec!["late".to_string()],
+            ..Default::default()
+        };
+        assert!(matches!(
+            db.polls.edit_poll(&poll_id, "owner", add, &db).await,
+            Err(PollError::InvalidPoll(_))
+        ));
+        let vote_log = db.polls.vote_log(&poll_id, &db).await.unwrap();
+        assert_eq!(vote_log.counts_match, Some(true));
+
+        db.options
+            .collection
+            .update_one(doc! {"_id": options[1]}, doc! {"$inc": {"votes_count": 5}})
+            .await
+            .unwrap();
+        let vote_log = db.polls.vote_log(&poll_id, &db).await.unwrap();
+        assert_eq!(vote_log.counts_match, Some(false));
+
+        // resetting reopens the poll, the log keeps its receipts and records the reset
+        db.polls.reset_poll(&poll_id, &db, "owner").await.unwrap();
+        let vote_log = db.polls.vote_log(&poll_id, &db).await.unwrap();
+        assert!(vote_log.final_digest.is_none());
+        let poll = db.polls.find(&poll_id).await.unwrap().unwrap();
+        assert!(!poll.is_closed());
+        let proof = db
+            .polls
+            .receipt_proof(&poll_id, &receipts[0].hash, &db)
+            .await
+            .unwrap();
+        assert_eq!(
+            proof.path.last().map(|entry| entry.kind),
+            Some(ReceiptKind::Reset)
+        );
+        let mut path = vec![proof.entry];
+        path.extend(proof.path);
+        assert_eq!(
+            receipts_repo::verify_path(&path),
+            proof.head.map(|head| head.hash)
+        );
+    }
+
+    #[test]
+    fn hidden_results_open_up_as_the_policy_allows() {
+        use ResultsVisibility::*;
+        // (policy, owner, voted, closed) => visible
+        let cases = [
+            (Always, false, false, false, true),
+            (AfterVote, false, false, false, false),
+            (AfterVote, false, true, false, true),
+            (AfterVote, false, false, true, true),
+            (AfterClose, false, true, false, false),
+            (AfterClose, false, false, true, true),
+            (OwnerOnly, false, true, true, false),
+            (OwnerOnly, true, false, false, true),
+        ];
+        for (policy, is_owner, has_voted, closed, visible) in cases {
+            assert_eq!(
+                policy.allows(is_owner, has_voted, closed),
+                visible,
+                "{:?} owner={} voted={} closed={}",
+                policy,
+                is_owner,
+                has_voted,
+                closed
+            );
+        }
+
+        let mut poll = test_poll("poll", Vec::new());
+        assert!(!poll.is_closed());
+        poll.secret_ballot = true;
+        assert!(!poll.owner_sees_results());
+        poll.closes_at = Some(Utc::now() - chrono::Duration::minutes(1));
+        assert!(poll.is_closed());
+        assert!(poll.owner_sees_results());
+        // scheduled, not opened yet
+        let mut poll = test_poll("poll", Vec::new());
+        poll.is_open = false;
+        poll.opens_at = Some(Utc::now() + chrono::Duration::minutes(1));
+        assert!(!poll.is_closed());
+    }
+
+    fn test_poll(poll_id: &str, options: Vec<ObjectId>) -> Poll {
+        Poll {
+            id: poll_id.to_string(),
+            title: "test".to_string(),
+            description: None,
+            owner_id: "owner".to_string(),
+            options,
+            is_open: true,
+            poll_type: PollType::Single,
+            allow_vote_change: false,
+            secret_ballot: false,
+            visibility: Visibility::Public,
+            results_visibility: ResultsVisibility::Always,
+            access_code: None,
+            opens_at: None,
+            closes_at: None,
+            closed_at: None,
+            deleted_at: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            history: Vec::new(),
+            chain_head: None,
+            final_digest: None,
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use anyhow::Result;
+use futures::TryStreamExt;
+use log::error;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    options::IndexOptions,
+    ClientSession, Collection, Database, IndexModel,
+};
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+
+use super::{polls_repo::Poll, votes_repo::Ballot};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptKind {
+    Cast,
+    Change,
+    Retract,
+    // the owner cleared every vote, entries before it no longer count
+    Reset,
+}
+
+/// One link of a poll's append-only vote log. It only commits to the ballot
+/// through a salted hash, and like secret ballots it carries no user or
+/// timestamp, so publishing the log gives away nothing about who voted how.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChainEntry {
+    // "<poll id>:<seq>", an ObjectId would leak when the vote came in
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub poll_id: String,
+    pub seq: i64,
+    pub kind: ReceiptKind,
+    pub commitment: String,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+/// Latest entry of the log, kept on the poll so appends are serialized by
+/// the write conflicts on the poll document.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChainHead {
+    pub seq: i64,
+    pub hash: String,
+}
+
+/// Handed to the voter once. The nonce is never stored, with it and their
+/// ballot the voter can recompute `commitment` and follow the log to its head.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Receipt {
+    pub poll_id: String,
+    pub seq: i64,
+    pub kind: ReceiptKind,
+    pub nonce: String,
+    pub commitment: String,
+    pub hash: String,
+}
+
+pub struct ReceiptRepo {
+    pub collection: Collection<ChainEntry>,
+}
+
+impl ReceiptRepo {
+    pub async fn init(db: &Database) -> Result<Self, Box<dyn Error>> {
+        let receipts_collection: Collection<ChainEntry> = db.collection("receipts");
+        let indexes = vec![
+            IndexModel::builder()
+                .keys(doc! {"poll_id": 1, "seq": 1})
+                .options(
+                    IndexOptions::builder()
+                        .unique(true)
+                        .name(Some("poll_log".to_string()))
+                        .build(),
+                )
+                .build(),
+            IndexModel::builder()
+                .keys(doc! {"poll_id": 1, "hash": 1})
+                .options(
+                    IndexOptions::builder()
+                        .name(Some("receipt_hash".to_string()))
+                        .build(),
+                )
+                .build(),
+        ];
+
+        if let Err(e) = receipts_collection.create_indexes(indexes).await {
+            error!("Failed to create indexes on receipts: {:?}", e);
+        }
+
+        Ok(Self {
+            collection: receipts_collection,
+        })
+    }
+
+    /// Appends an entry for `ballot` (None when retracting) to `poll`'s log
+    /// and moves its head, inside the caller's transaction.
+    pub async fn append(
+        collection: &Collection<ChainEntry>,
+        polls: &Collection<Poll>,
+        poll: &Poll,
+        kind: ReceiptKind,
+        ballot: Option<&Ballot>,
+        session: &mut ClientSession,
+    ) -> mongodb::error::Result<Receipt> {
+        let nonce = nanoid!(32);
+        let commitment = commitment(&poll.id, &nonce, ballot);
+        let (seq, prev_hash) = match &poll.chain_head {
+            Some(head) => (head.seq + 1, head.hash.clone()),
+            None => (0, genesis(&poll.id)),
+        };
+        let hash = chain_hash(&prev_hash, seq, kind, &commitment);
+        let entry = ChainEntry {
+            id: format!("{}:{}", poll.id, seq),
+            poll_id: poll.id.clone(),
+            seq,
+            kind,
+            commitment: commitment.clone(),
+            prev_hash,
+            hash: hash.clone(),
+        };
+        collection.insert_one(&entry).session(&mut *session).await?;
+        let head = ChainHead {
+            seq,
+            hash: hash.clone(),
+        };
+        polls
+            .update_one(
+                doc! {"id": &poll.id},
+                doc! {"$set": {"chain_head": mongodb::bson::to_bson(&head)?}},
+            )
+            .session(&mut *session)
+            .await?;
+        Ok(Receipt {
+            poll_id: poll.id.clone(),
+            seq,
+            kind,
+            nonce,
+            commitment,
+            hash,
+        })
+    }
+
+    pub async fn find_by_hash(&self, poll_id: &str, hash: &str) -> Result<Option<ChainEntry>> {
+        let filter = doc! {"poll_id": poll_id, "hash": hash};
+        Ok(self.collection.find_one(filter).await?)
+    }
+
+    /// Entries from `seq` on, enough to walk from a receipt to the head.
+    pub async fn find_from(&self, poll_id: &str, seq: i64) -> Result<Vec<ChainEntry>> {
+        Ok(self
+            .collection
+            .find(doc! {"poll_id": poll_id, "seq": {"$gte": seq}})
+            .sort(doc! {"seq": 1})
+            .await?
+            .try_collect()
+            .await?)
+    }
+}
+
+fn sha256(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        // length prefixed so "ab"+"c" and "a"+"bc" hash differently
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Where a poll's log starts, so logs of different polls can't be spliced together.
+pub fn genesis(poll_id: &str) -> String {
+    sha256(&[b"genesis", poll_id.as_bytes()])
+}
+
+pub fn commitment(poll_id: &str, nonce: &str, ballot: Option<&Ballot>) -> String {
+    let ballot = ballot
+        .map(|ballot| serde_json::to_vec(ballot).unwrap_or_default())
+        .unwrap_or_default();
+    sha256(&[poll_id.as_bytes(), nonce.as_bytes(), &ballot])
+}
+
+pub fn chain_hash(prev_hash: &str, seq: i64, kind: ReceiptKind, commitment: &str) -> String {
+    let kind = serde_json::to_vec(&kind).unwrap_or_default();
+    sha256(&[
+        prev_hash.as_bytes(),
+        &seq.to_be_bytes(),
+        &kind,
+        commitment.as_bytes(),
+    ])
+}
+
+/// Seals the log head together with every option's counter when the poll
+/// closes, `counts` in the poll's option order. It isn't keyed and lives on
+/// the poll, so whoever can write the poll can recompute it. It only proves
+/// anything against a copy taken elsewhere when the poll closed.
+pub fn final_digest(poll_id: &str, head: Option<&ChainHead>, counts: &[(ObjectId, u64)]) -> String {
+    let head = head
+        .map(|head| head.hash.clone())
+        .unwrap_or_else(|| genesis(poll_id));
+    let counts: Vec<u8> = counts
+        .iter()
+        .flat_map(|(option_id, votes_count)| {
+            let mut bytes = option_id.bytes().to_vec();
+            bytes.extend(votes_count.to_be_bytes());
+            bytes
+        })
+        .collect();
+    sha256(&[head.as_bytes(), &counts])
+}
+
+/// Recomputes every hash of `entries`, which must be consecutive, returning
+/// the hash they end on.
+pub fn verify_path(entries: &[ChainEntry]) -> Option<String> {
+    let mut prev: Option<&ChainEntry> = None;
+    for entry in entries {
+        if let Some(prev) = prev {
+            if entry.seq != prev.seq + 1 || entry.prev_hash != prev.hash {
+                return None;
+            }
+        }
+        if chain_hash(&entry.prev_hash, entry.seq, entry.kind, &entry.commitment) != entry.hash {
+            return None;
+        }
+        prev = Some(entry);
+    }
+    prev.map(|entry| entry.hash.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(poll_id: &str, ballots: &[Ballot]) -> Vec<ChainEntry> {
+        let mut prev_hash = genesis(poll_id);
+        ballots
+            .iter()
+            .enumerate()
+            .map(|(seq, ballot)| {
+                let commitment = commitment(poll_id, "nonce", Some(ballot));
+                let hash = chain_hash(&prev_hash, seq as i64, ReceiptKind::Cast, &commitment);
+                let entry = ChainEntry {
+                    id: format!("{}:{}", poll_id, seq),
+                    poll_id: poll_id.to_string(),
+                    seq: seq as i64,
+                    kind: ReceiptKind::Cast,
+                    commitment,
+                    prev_hash: prev_hash.clone(),
+                    hash: hash.clone(),
+                };
+                prev_hash = hash;
+                entry
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tampering_with_the_log_or_the_counts_is_detected() {
+        let options: Vec<ObjectId> = (0..2).map(|_| ObjectId::new()).collect();
+        let ballots: Vec<Ballot> = options
+            .iter()
+            .map(|option_id| Ballot::Single {
+                option_id: *option_id,
+            })
+            .collect();
+        let entries = log("poll", &ballots);
+        let head = entries.last().unwrap().hash.clone();
+        assert_eq!(verify_path(&entries), Some(head.clone()));
+        assert_eq!(verify_path(&entries[1..]), Some(head.clone()));
+
+        // swapping the ballot behind an entry breaks its hash
+        let mut forged = entries.clone();
+        forged[0].commitment = commitment("poll", "nonce", Some(&ballots[1]));
+        assert_eq!(verify_path(&forged), None);
+        // so does dropping an entry from the middle
+        let mut gapped = log(
+            "poll",
+            &[ballots[0].clone(), ballots[0].clone(), ballots[1].clone()],
+        );
+        gapped.remove(1);
+        assert_eq!(verify_path(&gapped), None);
+
+        let head = ChainHead { seq: 1, hash: head };
+        let counts = [(options[0], 1), (options[1], 1)];
+        let sealed = final_digest("poll", Some(&head), &counts);
+        assert_eq!(final_digest("poll", Some(&head), &counts), sealed);
+        assert_ne!(
+            final_digest("poll", Some(&head), &[(options[0], 2), (options[1], 0)]),
+            sealed
+        );
+    }
+}
@@ -1,24 +1,41 @@
 use anyhow::Result;
-use log::error;
+use log::{error, info};
 use mongodb::{
-    bson::doc,
+    bson::{self, doc},
     results::{DeleteResult, InsertOneResult},
     Collection, Database, IndexModel,
 };
 use serde::{Deserialize, Serialize};
-use std::error::Error;
+use std::{error::Error, time::Duration};
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RegState {
     pub username: String,
     pub uuid: String,
     pub reg_state: serde_json::Value,
+    pub created_at: bson::DateTime,
 }
 pub struct RegStateRepo {
     collection: Collection<RegState>,
+    // ceremonies of signed in users adding another passkey, kept apart so they
+    // can't collide with a registration of the same username
+    passkey_collection: Collection<RegState>,
+    ttl: Duration,
+}
+
+fn ttl_index(ttl: Duration) -> IndexModel {
+    IndexModel::builder()
+        .keys(doc! {"created_at": 1})
+        .options(
+            mongodb::options::IndexOptions::builder()
+                .expire_after(Some(ttl))
+                .name(Some("expire_reg_states".to_string()))
+                .build(),
+        )
+        .build()
 }
 
 impl RegStateRepo {
-    pub async fn init(db: &Database) -> Result<Self, Box<dyn Error>> {
+    pub async fn init(db: &Database, ttl: Duration) -> Result<Self, Box<dyn Error>> {
         let reg_state_collection: Collection<RegState> = db.collection("reg_states");
         let index = IndexModel::builder()
             .keys(doc! {"username": 1})
@@ -33,19 +50,61 @@ impl RegStateRepo {
         if let Err(e) = reg_state_collection.create_index(index).await {
             error!("Failed to create index on `username`: {:?}", e);
         }
+
+        if let Err(e) = reg_state_collection.create_index(ttl_index(ttl)).await {
+            error!("Failed to create ttl index on `created_at`: {:?}", e);
+        }
+
+        let passkey_collection: Collection<RegState> = db.collection("passkey_reg_states");
+        let passkey_index = IndexModel::builder()
+            .keys(doc! {"username": 1})
+            .options(
+                mongodb::options::IndexOptions::builder()
+                    .unique(true)
+                    .name(Some("unique_username".to_string()))
+                    .build(),
+            )
+            .build();
+
+        if let Err(e) = passkey_collection.create_index(passkey_index).await {
+            error!("Failed to create index on `username`: {:?}", e);
+        }
+        if let Err(e) = passkey_collection.create_index(ttl_index(ttl)).await {
+            error!("Failed to create ttl index on `created_at`: {:?}", e);
+        }
+
+        // rows written before states were timestamped are never picked up by the ttl index
+        match reg_state_collection
+            .delete_many(doc! {"created_at": {"$exists": false}})
+            .await
+        {
+            Ok(result) if result.deleted_count > 0 => {
+                info!("Removed {} untimed reg states", result.deleted_count)
+            }
+            Ok(_) => (),
+            Err(e) => error!("Failed removing untimed reg states: {:?}", e),
+        }
+
         Ok(Self {
             collection: reg_state_collection,
+            passkey_collection,
+            ttl,
         })
     }
 
+    // the ttl monitor only runs once a minute, so expiry is also enforced on read
+    fn live_since(&self) -> bson::DateTime {
+        bson::DateTime::from_millis(
+            bson::DateTime::now().timestamp_millis() - self.ttl.as_millis() as i64,
+        )
+    }
+
     pub async fn insert(&self, reg_state_entry: RegState) -> Result<InsertOneResult> {
         let username = &reg_state_entry.username;
-        let _reg_state_alread_exists = match self.is_exists(username).await {
+        match self.is_exists(username).await {
             Ok(data) => {
                 if data {
                     self.delete_by_username(username).await.unwrap();
-                } else {
-                    ()
                 }
             }
             Err(e) => {
@@ -64,7 +123,7 @@ impl RegStateRepo {
     }
 
     pub async fn find_by_username(&self, username: &str) -> Result<Option<RegState>> {
-        let filter = doc! {"username": username};
+        let filter = doc! {"username": username, "created_at": {"$gt": self.live_since()}};
         let result = self.collection.find_one(filter).await.map_err(|e| {
             error!("Error finding reg state by username {}", e);
             anyhow::Error::new(e)
@@ -78,6 +137,10 @@ impl RegStateRepo {
         Ok(self.collection.find_one(filter).await?.is_some())
     }
 
+    pub async fn is_live(&self, username: &str) -> Result<bool> {
+        Ok(self.find_by_username(username).await?.is_some())
+    }
+
     pub async fn delete_by_username(&self, username: &str) -> Result<DeleteResult> {
         let filter = doc! {"username": username};
         let result = self.collection.delete_many(filter).await.map_err(|e| {
@@ -86,4 +149,40 @@ impl RegStateRepo {
         });
         result
     }
+
+    /// Frees usernames held by registrations that were started but never finished.
+    pub async fn delete_expired(&self) -> Result<u64> {
+        let filter = doc! {"created_at": {"$lte": self.live_since()}};
+        let result = self.collection.delete_many(filter).await.map_err(|e| {
+            error!("Error deleting expired reg states {}", e);
+            anyhow::Error::new(e)
+        })?;
+        Ok(result.deleted_count)
+    }
+
+    /// Stores the state of `username` adding a passkey, replacing any earlier attempt.
+    pub async fn insert_passkey(&self, reg_state_entry: RegState) -> Result<()> {
+        let filter = doc! {"username": &reg_state_entry.username};
+        self.passkey_collection
+            .replace_one(filter, reg_state_entry)
+            .upsert(true)
+            .await
+            .map_err(|e| {
+                error!("Error inserting passkey reg state {}", e);
+                anyhow::Error::new(e)
+            })?;
+        Ok(())
+    }
+
+    // removing the state as it is read makes every challenge single use
+    pub async fn take_passkey(&self, username: &str) -> Result<Option<RegState>> {
+        let filter = doc! {"username": username, "created_at": {"$gt": self.live_since()}};
+        self.passkey_collection
+            .find_one_and_delete(filter)
+            .await
+            .map_err(|e| {
+                error!("Error taking passkey reg state {}", e);
+                anyhow::Error::new(e)
+            })
+    }
 }
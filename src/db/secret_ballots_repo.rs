@@ -0,0 +1,108 @@
+use anyhow::Result;
+use futures::TryStreamExt;
+use log::error;
+use mongodb::{
+    bson::{self, doc},
+    options::IndexOptions,
+    ClientSession, Collection, Cursor, Database, IndexModel,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+use super::votes_repo::Ballot;
+
+// ballots of a poll are spread over this many documents to stay clear of
+// the 16MB document limit
+const BUCKETS: u32 = 16;
+
+/// Ballots of a secret ballot poll. They carry no user, id or timestamp, and
+/// every push re-sorts the bucket by a random key, so neither the contents
+/// nor the order of a bucket tell which ballot came in when. Who took part is
+/// recorded separately in the votes ledger.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BallotBucket {
+    // "<poll id>:<bucket>"
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub poll_id: String,
+    pub ballots: Vec<SealedBallot>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SealedBallot {
+    // random, only there to shuffle the bucket
+    pub k: i64,
+    pub ballot: Ballot,
+}
+
+pub struct SecretBallotRepo {
+    pub collection: Collection<BallotBucket>,
+}
+
+impl SecretBallotRepo {
+    pub async fn init(db: &Database) -> Result<Self, Box<dyn Error>> {
+        let secret_ballots_collection: Collection<BallotBucket> = db.collection("secret_ballots");
+        // also creates the collection up front, upserts inside transactions need it to exist
+        let index = IndexModel::builder()
+            .keys(doc! {"poll_id": 1})
+            .options(
+                IndexOptions::builder()
+                    .name(Some("poll_buckets".to_string()))
+                    .build(),
+            )
+            .build();
+
+        if let Err(e) = secret_ballots_collection.create_index(index).await {
+            error!("Failed to create indexes on secret ballots: {:?}", e);
+        }
+
+        Ok(Self {
+            collection: secret_ballots_collection,
+        })
+    }
+
+    /// Drops `ballot` into a random bucket of the poll, inside the caller's transaction.
+    pub async fn cast(
+        collection: &Collection<BallotBucket>,
+        poll_id: &str,
+        ballot: &Ballot,
+        session: &mut ClientSession,
+    ) -> mongodb::error::Result<()> {
+        let (bucket, k) = {
+            let mut rng = rand::thread_rng();
+            (rng.gen_range(0..BUCKETS), rng.gen::<i64>())
+        };
+        let sealed = bson::to_bson(&SealedBallot {
+            k,
+            ballot: ballot.clone(),
+        })?;
+        collection
+            .update_one(
+                doc! {"_id": format!("{}:{}", poll_id, bucket), "poll_id": poll_id},
+                doc! {"$push": {"ballots": {"$each": [sealed], "$sort": {"k": 1}}}},
+            )
+            .upsert(true)
+            .session(session)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn find_ballots(&self, poll_id: &str) -> Result<Vec<Ballot>> {
+        let buckets: Vec<BallotBucket> = self
+            .collection
+            .find(doc! {"poll_id": poll_id})
+            .await?
+            .try_collect()
+            .await?;
+        Ok(buckets
+            .into_iter()
+            .flat_map(|bucket| bucket.ballots)
+            .map(|sealed| sealed.ballot)
+            .collect())
+    }
+
+    pub async fn stream_buckets(&self, poll_id: &str) -> Result<Cursor<BallotBucket>> {
+        Ok(self.collection.find(doc! {"poll_id": poll_id}).await?)
+    }
+}
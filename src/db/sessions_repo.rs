@@ -0,0 +1,166 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use futures::TryStreamExt;
+use log::error;
+use mongodb::{
+    bson::{self, doc},
+    options::IndexOptions,
+    Collection, Database, IndexModel,
+};
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+use crate::utils::jwt::REFRESH_TOKEN_TTL_DAYS;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Session {
+    pub session_id: String,
+    pub username: String,
+    // id of the only refresh token of this session that may still be redeemed
+    pub refresh_token_id: String,
+    pub revoked: bool,
+    pub created_at: DateTime<Utc>,
+    pub last_refreshed_at: DateTime<Utc>,
+    // stored as a bson date so the ttl index can clean up dead sessions
+    pub expires_at: bson::DateTime,
+}
+
+pub struct SessionRepo {
+    collection: Collection<Session>,
+}
+
+fn refresh_expiry() -> bson::DateTime {
+    let expires_at = Utc::now() + Duration::days(REFRESH_TOKEN_TTL_DAYS);
+    bson::DateTime::from_millis(expires_at.timestamp_millis())
+}
+
+impl SessionRepo {
+    pub async fn init(db: &Database) -> Result<Self, Box<dyn Error>> {
+        let sessions_collection: Collection<Session> = db.collection("sessions");
+        let indexes = vec![
+            IndexModel::builder()
+                .keys(doc! {"session_id": 1})
+                .options(
+                    IndexOptions::builder()
+                        .unique(true)
+                        .name(Some("unique_session_id".to_string()))
+                        .build(),
+                )
+                .build(),
+            IndexModel::builder()
+                .keys(doc! {"username": 1})
+                .options(
+                    IndexOptions::builder()
+                        .name(Some("username".to_string()))
+                        .build(),
+                )
+                .build(),
+            IndexModel::builder()
+                .keys(doc! {"expires_at": 1})
+                .options(
+                    IndexOptions::builder()
+                        .expire_after(Some(std::time::Duration::from_secs(0)))
+                        .name(Some("expire_sessions".to_string()))
+                        .build(),
+                )
+                .build(),
+        ];
+
+        if let Err(e) = sessions_collection.create_indexes(indexes).await {
+            error!("Failed to create indexes on sessions: {:?}", e);
+        }
+
+        Ok(Self {
+            collection: sessions_collection,
+        })
+    }
+
+    pub async fn create(&self, username: &str) -> Result<Session> {
+        let session = Session {
+            session_id: nanoid!(),
+            username: username.to_string(),
+            refresh_token_id: nanoid!(),
+            revoked: false,
+            created_at: Utc::now(),
+            last_refreshed_at: Utc::now(),
+            expires_at: refresh_expiry(),
+        };
+        self.collection.insert_one(&session).await.map_err(|e| {
+            error!("Error creating session {}", e);
+            anyhow::Error::new(e)
+        })?;
+        Ok(session)
+    }
+
+    pub async fn find_active(&self, session_id: &str) -> Result<Option<Session>> {
+        let filter = doc! {
+            "session_id": session_id,
+            "revoked": false,
+            "expires_at": {"$gt": bson::DateTime::now()}
+        };
+        self.collection.find_one(filter).await.map_err(|e| {
+            error!("Error finding session {}", e);
+            anyhow::Error::new(e)
+        })
+    }
+
+    pub async fn find_by_username(&self, username: &str) -> Result<Vec<Session>> {
+        let filter = doc! {
+            "username": username,
+            "revoked": false,
+            "expires_at": {"$gt": bson::DateTime::now()}
+        };
+        let cursor = self
+            .collection
+            .find(filter)
+            .sort(doc! {"last_refreshed_at": -1})
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Swaps the redeemable refresh token id. Returns false when `current_id`
+    /// is no longer the live token, which means the token was already used.
+    pub async fn rotate(&self, session_id: &str, current_id: &str, next_id: &str) -> Result<bool> {
+        let filter = doc! {
+            "session_id": session_id,
+            "refresh_token_id": current_id,
+            "revoked": false
+        };
+        let update = doc! {
+            "$set": {
+                "refresh_token_id": next_id,
+                "last_refreshed_at": bson::to_bson(&Utc::now())?,
+                "expires_at": refresh_expiry()
+            }
+        };
+        let result = self.collection.update_one(filter, update).await?;
+        Ok(result.modified_count > 0)
+    }
+
+    pub async fn revoke(&self, session_id: &str, username: &str) -> Result<bool> {
+        let filter = doc! {"session_id": session_id, "username": username};
+        let result = self
+            .collection
+            .update_one(filter, doc! {"$set": {"revoked": true}})
+            .await
+            .map_err(|e| {
+                error!("Error revoking session {}", e);
+                anyhow::Error::new(e)
+            })?;
+        Ok(result.matched_count > 0)
+    }
+
+    pub async fn revoke_all(&self, username: &str) -> Result<u64> {
+        let filter = doc! {"username": username, "revoked": false};
+        let result = self
+            .collection
+            .update_many(filter, doc! {"$set": {"revoked": true}})
+            .await
+            .map_err(|e| {
+                error!("Error revoking sessions of {} {}", username, e);
+                anyhow::Error::new(e)
+            })?;
+        Ok(result.modified_count)
+    }
+}
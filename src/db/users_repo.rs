@@ -14,11 +14,10 @@ pub struct User {
     pub id: Option<ObjectId>,
     pub username: String,
     pub uuid: String,
-    pub sk: serde_json::Value,
 }
 
 pub struct UserRepo {
-    collection: Collection<User>,
+    pub collection: Collection<User>,
 }
 
 impl UserRepo {
@@ -48,6 +47,14 @@ impl UserRepo {
         Ok(result)
     }
 
+    pub async fn search_by_uuid(&self, uuid: &str) -> Result<Option<User>> {
+        let filter = doc! {"uuid": uuid};
+        self.collection.find_one(filter).await.map_err(|e| {
+            error!("Error finding user by uuid {}", e);
+            anyhow::Error::new(e)
+        })
+    }
+
     pub async fn get_user_id(&self, username: &str) -> Result<Option<ObjectId>> {
         let filter = doc! {"username": username};
         let result = match self.collection.find_one(filter).await? {
@@ -0,0 +1,310 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use log::{error, info};
+use mongodb::{
+    bson::{doc, oid::ObjectId, Document},
+    error::{ErrorKind, WriteFailure},
+    options::IndexOptions,
+    Collection, Cursor, Database, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+use crate::tabulation::quadratic::Allocation;
+
+/// One row per ballot cast. The (poll_id, user_id) unique index is what
+/// guarantees a user votes at most once per poll.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Vote {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub poll_id: String,
+    // None for votes migrated from the old `voters` array, which never recorded
+    // the choice, and for secret ballots, which are stored apart
+    pub option_id: Option<ObjectId>,
+    pub user_id: String,
+    // the full ballot for poll types that pick more than one option
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ballot: Option<Ballot>,
+    pub created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub metadata: Document,
+}
+
+/// What a voter submitted, shaped by the poll's `PollType`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Ballot {
+    Single { option_id: ObjectId },
+    Approval { option_ids: Vec<ObjectId> },
+    // most preferred first
+    Ranked { rankings: Vec<ObjectId> },
+    Rating { scores: Vec<OptionScore> },
+    Quadratic { allocations: Vec<Allocation> },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OptionScore {
+    pub option_id: ObjectId,
+    pub score: i32,
+}
+
+impl Ballot {
+    /// Options whose `votes_count` goes up by one when this ballot is cast.
+    pub fn counted_options(&self) -> Vec<ObjectId> {
+        match self {
+            Ballot::Single { option_id } => vec![*option_id],
+            Ballot::Approval { option_ids } => option_ids.clone(),
+            // the counter tracks first preferences, the runoff is tabulated from the ledger
+            Ballot::Ranked { rankings } => rankings.first().copied().into_iter().collect(),
+            // counts how many times each option was rated
+            Ballot::Rating { scores } => scores.iter().map(|score| score.option_id).collect(),
+            // counts supporters, raw votes and credits are tabulated from the ledger
+            Ballot::Quadratic { allocations } => allocations
+                .iter()
+                .filter(|allocation| allocation.votes > 0)
+                .map(|allocation| allocation.option_id)
+                .collect(),
+        }
+    }
+
+    /// Whether this ballot says anything about `option_id`, i.e. it would
+    /// lose meaning if the option went away.
+    pub fn references(&self, option_id: &ObjectId) -> bool {
+        match self {
+            Ballot::Single { option_id: picked } => picked == option_id,
+            Ballot::Approval { option_ids } => option_ids.contains(option_id),
+            Ballot::Ranked { rankings } => rankings.contains(option_id),
+            Ballot::Rating { scores } => scores.iter().any(|score| &score.option_id == option_id),
+            Ballot::Quadratic { allocations } => allocations
+                .iter()
+                .any(|allocation| &allocation.option_id == option_id && allocation.votes > 0),
+        }
+    }
+}
+
+impl Vote {
+    pub fn new(poll_id: &str, ballot: Ballot, user_id: &str) -> Self {
+        let (option_id, ballot) = match ballot {
+            Ballot::Single { option_id } => (Some(option_id), None),
+            ballot => (None, Some(ballot)),
+        };
+        Vote {
+            id: ObjectId::new(),
+            poll_id: poll_id.to_string(),
+            option_id,
+            user_id: user_id.to_string(),
+            ballot,
+            created_at: Utc::now(),
+            changed_at: None,
+            metadata: Document::new(),
+        }
+    }
+
+    /// A vote on a secret ballot poll, only records that `user_id` took part.
+    pub fn participation(poll_id: &str, user_id: &str) -> Self {
+        Vote {
+            id: ObjectId::new(),
+            poll_id: poll_id.to_string(),
+            option_id: None,
+            user_id: user_id.to_string(),
+            ballot: None,
+            created_at: Utc::now(),
+            changed_at: None,
+            metadata: doc! {"secret_ballot": true},
+        }
+    }
+
+    /// The ballot this vote was cast with, None for migrated votes that never
+    /// recorded it and for secret ballots.
+    pub fn ballot(&self) -> Option<Ballot> {
+        match (&self.ballot, self.option_id) {
+            (Some(ballot), _) => Some(ballot.clone()),
+            (None, Some(option_id)) => Some(Ballot::Single { option_id }),
+            (None, None) => None,
+        }
+    }
+}
+
+pub struct VoteRepo {
+    pub collection: Collection<Vote>,
+}
+
+pub fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(
+        e.kind.as_ref(),
+        ErrorKind::Write(WriteFailure::WriteError(write_error)) if write_error.code == 11000
+    )
+}
+
+/// Aggregation stages adding a `total_votes` field to poll documents,
+/// counted from the ledger rather than stored on the poll.
+pub fn total_votes_stages() -> Vec<Document> {
+    vec![
+        doc! {
+            "$lookup": {
+                "from": "votes",
+                "let": {"poll_id": "$id"},
+                "pipeline": [
+                    {"$match": {"$expr": {"$eq": ["$poll_id", "$$poll_id"]}}},
+                    {"$count": "count"}
+                ],
+                "as": "vote_count"
+            }
+        },
+        doc! {
+            "$addFields": {
+                "total_votes": {
+                    "$toLong": {"$ifNull": [{"$arrayElemAt": ["$vote_count.count", 0]}, 0]}
+                }
+            }
+        },
+        doc! {"$unset": "vote_count"},
+    ]
+}
+
+impl VoteRepo {
+    pub async fn init(db: &Database) -> Result<Self, Box<dyn Error>> {
+        let votes_collection: Collection<Vote> = db.collection("votes");
+        let indexes = vec![
+            IndexModel::builder()
+                .keys(doc! {"poll_id": 1, "user_id": 1})
+                .options(
+                    IndexOptions::builder()
+                        .unique(true)
+                        .name(Some("unique_poll_voter".to_string()))
+                        .build(),
+                )
+                .build(),
+            IndexModel::builder()
+                .keys(doc! {"user_id": 1, "created_at": -1})
+                .options(
+                    IndexOptions::builder()
+                        .name(Some("user_history".to_string()))
+                        .build(),
+                )
+                .build(),
+        ];
+
+        if let Err(e) = votes_collection.create_indexes(indexes).await {
+            error!("Failed to create indexes on votes: {:?}", e);
+        }
+
+        Ok(Self {
+            collection: votes_collection,
+        })
+    }
+
+    /// Records a vote. Returns false when the user already has a vote on the poll.
+    pub async fn insert(&self, vote: &Vote) -> Result<bool> {
+        match self.collection.insert_one(vote).await {
+            Ok(_) => Ok(true),
+            Err(e) if is_duplicate_key(&e) => Ok(false),
+            Err(e) => {
+                error!("Error inserting vote {}", e);
+                Err(anyhow::Error::new(e))
+            }
+        }
+    }
+
+    pub async fn has_voted(&self, poll_id: &str, user_id: &str) -> Result<bool> {
+        let filter = doc! {"poll_id": poll_id, "user_id": user_id};
+        Ok(self.collection.find_one(filter).await?.is_some())
+    }
+
+    pub async fn find_ballots(&self, poll_id: &str) -> Result<Vec<Ballot>> {
+        let filter = doc! {"poll_id": poll_id, "ballot": {"$exists": true}};
+        let votes: Vec<Vote> = self.collection.find(filter).await?.try_collect().await?;
+        Ok(votes.into_iter().filter_map(|vote| vote.ballot).collect())
+    }
+
+    /// Every vote of the poll, oldest first, for exports too large to collect.
+    pub async fn stream_by_poll(&self, poll_id: &str) -> Result<Cursor<Vote>> {
+        Ok(self
+            .collection
+            .find(doc! {"poll_id": poll_id})
+            .sort(doc! {"_id": 1})
+            .await?)
+    }
+
+    /// Votes left behind by polls that no longer exist.
+    pub async fn find_orphans(&self) -> Result<Vec<ObjectId>> {
+        let pipeline = vec![
+            doc! {
+                "$lookup": {
+                    "from": "polls",
+                    "localField": "poll_id",
+                    "foreignField": "id",
+                    "as": "poll"
+                }
+            },
+            doc! {"$match": {"poll": {"$size": 0}}},
+            doc! {"$project": {"_id": 1}},
+        ];
+        let orphans: Vec<Document> = self
+            .collection
+            .aggregate(pipeline)
+            .await?
+            .try_collect()
+            .await?;
+        Ok(orphans
+            .iter()
+            .filter_map(|orphan| orphan.get_object_id("_id").ok())
+            .collect())
+    }
+
+    pub async fn delete_many(&self, vote_ids: &[ObjectId]) -> Result<u64> {
+        let result = self
+            .collection
+            .delete_many(doc! {"_id": {"$in": vote_ids}})
+            .await
+            .map_err(|e| {
+                error!("Error deleting votes {}", e);
+                anyhow::Error::new(e)
+            })?;
+        Ok(result.deleted_count)
+    }
+
+    /// Moves the usernames kept in the old `Poll.voters` array into the ledger.
+    pub async fn migrate_legacy_voters(&self, db: &Database) -> Result<()> {
+        let polls: Collection<Document> = db.collection("polls");
+        let mut cursor = polls.find(doc! {"voters": {"$exists": true}}).await?;
+        let mut migrated = 0;
+        while let Some(poll) = cursor.try_next().await? {
+            let poll_id = poll.get_str("id")?.to_string();
+            let created_at = poll
+                .get("updated_at")
+                .cloned()
+                .and_then(|updated_at| mongodb::bson::from_bson(updated_at).ok())
+                .unwrap_or_else(Utc::now);
+            for voter in poll.get_array("voters")? {
+                let Some(user_id) = voter.as_str() else {
+                    continue;
+                };
+                let vote = Vote {
+                    id: ObjectId::new(),
+                    poll_id: poll_id.clone(),
+                    option_id: None,
+                    user_id: user_id.to_string(),
+                    ballot: None,
+                    created_at,
+                    changed_at: None,
+                    metadata: doc! {"migrated_from": "poll_voters"},
+                };
+                if self.insert(&vote).await? {
+                    migrated += 1;
+                }
+            }
+            polls
+                .update_one(doc! {"id": &poll_id}, doc! {"$unset": {"voters": ""}})
+                .await?;
+        }
+        if migrated > 0 {
+            info!("Migrated {} legacy voters to the votes ledger", migrated);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt;
+
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use log::error;
+use mongodb::bson::oid::ObjectId;
+
+use crate::utils::json_responder::Response;
+
+pub type PollResult<T> = Result<T, PollError>;
+
+/// Why a poll operation was refused. Each variant carries a stable `code`
+/// clients can match on, the message is meant for people.
+#[derive(Debug)]
+pub enum PollError {
+    NotFound,
+    // the poll exists but belongs to someone else
+    Forbidden,
+    // not accepting votes: closed, not open yet or past its deadline
+    PollClosed,
+    VoteChangeNotAllowed,
+    AlreadyVoted,
+    // nothing to change or retract, or a migrated vote that never recorded its options
+    NoVote,
+    InvalidOption(String),
+    InvalidBallot(String),
+    InvalidPoll(String),
+    // the option is referenced by ballots and `discard_votes` wasn't set
+    OptionHasVotes(ObjectId),
+    // invite-only poll and no invite, or the invite/access code is wrong
+    InviteRequired,
+    InviteNotFound,
+    ReceiptNotFound,
+    // the poll's `results_visibility` keeps its results from the caller for now
+    ResultsHidden,
+    Database(anyhow::Error),
+}
+
+impl PollError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            PollError::NotFound => "poll_not_found",
+            PollError::Forbidden => "not_poll_owner",
+            PollError::PollClosed => "poll_closed",
+            PollError::VoteChangeNotAllowed => "vote_change_not_allowed",
+            PollError::AlreadyVoted => "already_voted",
+            PollError::NoVote => "no_vote",
+            PollError::InvalidOption(_) => "invalid_option",
+            PollError::InvalidBallot(_) => "invalid_ballot",
+            PollError::InvalidPoll(_) => "invalid_poll",
+            PollError::OptionHasVotes(_) => "option_has_votes",
+            PollError::InviteRequired => "invite_required",
+            PollError::InviteNotFound => "invite_not_found",
+            PollError::ReceiptNotFound => "receipt_not_found",
+            PollError::ResultsHidden => "results_hidden",
+            PollError::Database(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for PollError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PollError::NotFound => write!(f, "No such poll!"),
+            PollError::Forbidden => write!(f, "Only the owner can do that!"),
+            PollError::PollClosed => write!(f, "Poll isn't accepting votes!"),
+            PollError::VoteChangeNotAllowed => write!(f, "Poll doesn't allow changing votes!"),
+            PollError::AlreadyVoted => write!(f, "You've already voted on this poll!"),
+            PollError::NoVote => write!(f, "You have no vote on this poll that can be changed!"),
+            PollError::InvalidOption(reason)
+            | PollError::InvalidBallot(reason)
+            | PollError::InvalidPoll(reason) => write!(f, "{}", reason),
+            PollError::OptionHasVotes(option_id) => write!(
+                f,
+                "Option {} has votes, set discard_votes to remove it anyway!",
+                option_id
+            ),
+            PollError::InviteRequired => write!(f, "This poll is invite only!"),
+            PollError::InviteNotFound => write!(f, "No such invite!"),
+            PollError::ReceiptNotFound => write!(f, "No such receipt in this poll's log!"),
+            PollError::ResultsHidden => {
+                write!(f, "Results of this poll aren't visible to you yet!")
+            }
+            PollError::Database(_) => write!(f, "Something went wrong!"),
+        }
+    }
+}
+
+impl From<anyhow::Error> for PollError {
+    fn from(e: anyhow::Error) -> Self {
+        PollError::Database(e)
+    }
+}
+
+impl From<mongodb::error::Error> for PollError {
+    fn from(e: mongodb::error::Error) -> Self {
+        PollError::Database(anyhow::Error::new(e))
+    }
+}
+
+impl ResponseError for PollError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PollError::NotFound | PollError::InviteNotFound | PollError::ReceiptNotFound => {
+                StatusCode::NOT_FOUND
+            }
+            PollError::Forbidden | PollError::InviteRequired | PollError::ResultsHidden => {
+                StatusCode::FORBIDDEN
+            }
+            PollError::PollClosed
+            | PollError::VoteChangeNotAllowed
+            | PollError::AlreadyVoted
+            | PollError::NoVote
+            | PollError::OptionHasVotes(_) => StatusCode::CONFLICT,
+            PollError::InvalidOption(_)
+            | PollError::InvalidBallot(_)
+            | PollError::InvalidPoll(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            PollError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if let PollError::Database(e) = self {
+            error!("Poll operation failed {:?}", e);
+        }
+        Response::<()>::error_with_code(&self.to_string(), self.code(), self.status_code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn business_rejections_are_not_server_errors() {
+        assert_eq!(PollError::NotFound.status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(PollError::Forbidden.status_code(), StatusCode::FORBIDDEN);
+        assert_eq!(PollError::AlreadyVoted.status_code(), StatusCode::CONFLICT);
+        assert_eq!(
+            PollError::InvalidBallot("Rank every option of the poll!".to_string()).status_code(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            PollError::Database(anyhow::anyhow!("down")).status_code(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use actix_web::web::Bytes;
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+use mongodb::bson::{doc, oid::ObjectId};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::{options_repo::OptionModel, polls_repo::Poll, votes_repo::Ballot, DB},
+    models::poll_api_model::PollResults,
+};
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    // newline-delimited JSON, one object per line
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportData {
+    #[default]
+    Results,
+    Ballots,
+}
+
+impl ExportData {
+    pub fn name(self) -> &'static str {
+        match self {
+            ExportData::Results => "results",
+            ExportData::Ballots => "ballots",
+        }
+    }
+}
+
+/// One option a ballot says something about. `value` is 1 for a pick, the
+/// rank for ranked ballots (1 is most preferred), the score for ratings and
+/// the votes for quadratic ballots.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Choice {
+    pub option_id: ObjectId,
+    pub option_text: String,
+    pub value: i64,
+}
+
+/// A ballot without its voter. `ballot` only numbers the rows of one export,
+/// secret ballots come in no particular order and without timestamps.
+#[derive(Serialize, Debug)]
+pub struct ExportedBallot {
+    pub ballot: u64,
+    pub cast_at: Option<DateTime<Utc>>,
+    pub changed_at: Option<DateTime<Utc>>,
+    pub choices: Vec<Choice>,
+}
+
+// (cast_at, changed_at, ballot)
+type BallotRecord = (Option<DateTime<Utc>>, Option<DateTime<Utc>>, Ballot);
+
+/// Quotes a CSV field when it holds a comma, quote or line break, doubling
+/// any quotes inside (RFC 4180).
+pub fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+fn csv_row<S: AsRef<str>>(fields: &[S]) -> String {
+    let fields: Vec<Cow<str>> = fields
+        .iter()
+        .map(|field| csv_field(field.as_ref()))
+        .collect();
+    format!("{}\r\n", fields.join(","))
+}
+
+fn cell<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+fn timestamp(value: Option<DateTime<Utc>>) -> String {
+    cell(value.map(|value| value.to_rfc3339_opts(SecondsFormat::Millis, true)))
+}
+
+fn json_line<T: Serialize>(value: &T) -> Result<Bytes> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    Ok(Bytes::from(line))
+}
+
+/// The poll's results, one row per option as CSV or the whole `PollResults` as a single line.
+pub fn results(results: &PollResults, format: ExportFormat) -> Result<Bytes> {
+    if let ExportFormat::Ndjson = format {
+        return json_line(results);
+    }
+    let mut csv = csv_row(&[
+        "option_id",
+        "option_text",
+        "votes_count",
+        "votes_percentage",
+        "mean_score",
+        "median_score",
+        "score_std_dev",
+        "quadratic_votes",
+        "credits",
+    ]);
+    for option in &results.options {
+        let rating = option.rating.as_ref();
+        let quadratic = option.quadratic.as_ref();
+        csv.push_str(&csv_row(&[
+            option.id.to_hex(),
+            option.text.clone(),
+            option.votes_count.to_string(),
+            option.votes_percentage.to_string(),
+            cell(rating.and_then(|rating| rating.mean)),
+            cell(rating.and_then(|rating| rating.median)),
+            cell(rating.and_then(|rating| rating.std_dev)),
+            cell(quadratic.map(|quadratic| quadratic.votes)),
+            cell(quadratic.map(|quadratic| quadratic.credits)),
+        ]));
+    }
+    Ok(Bytes::from(csv))
+}
+
+/// Every ballot of the poll without its voter, read from the database as
+/// the response is written. CSV has one row per choice, NDJSON one line per ballot.
+pub async fn ballots(
+    poll: &Poll,
+    format: ExportFormat,
+    db: &DB,
+) -> Result<BoxStream<'static, Result<Bytes>>> {
+    let texts: HashMap<ObjectId, String> = db
+        .options
+        .collection
+        .find(doc! {"_id": {"$in": &poll.options}})
+        .await?
+        .try_collect::<Vec<OptionModel>>()
+        .await?
+        .into_iter()
+        .map(|option| (option._id, option.text))
+        .collect();
+
+    let records: BoxStream<'static, Result<BallotRecord>> = if poll.secret_ballot {
+        db.secret_ballots
+            .stream_buckets(&poll.id)
+            .await?
+            .map_ok(|bucket| {
+                stream::iter(
+                    bucket
+                        .ballots
+                        .into_iter()
+                        .map(|sealed| Ok::<_, mongodb::error::Error>((None, None, sealed.ballot))),
+                )
+            })
+            .try_flatten()
+            .map_err(anyhow::Error::new)
+            .boxed()
+    } else {
+        db.votes
+            .stream_by_poll(&poll.id)
+            .await?
+            // migrated votes that never recorded their options are left out
+            .try_filter_map(|vote| async move {
+                Ok(vote
+                    .ballot()
+                    .map(|ballot| (Some(vote.created_at), vote.changed_at, ballot)))
+            })
+            .map_err(anyhow::Error::new)
+            .boxed()
+    };
+
+    let header = match format {
+        ExportFormat::Csv => Some(Ok(Bytes::from(csv_row(&[
+            "ballot",
+            "cast_at",
+            "changed_at",
+            "option_id",
+            "option_text",
+            "value",
+        ])))),
+        ExportFormat::Ndjson => None,
+    };
+    let body = records.enumerate().map(move |(n, record)| {
+        let (cast_at, changed_at, ballot) = record?;
+        let exported = ExportedBallot {
+            ballot: n as u64 + 1,
+            cast_at,
+            changed_at,
+            choices: choices(&ballot, &texts),
+        };
+        encode_ballot(&exported, format)
+    });
+    Ok(stream::iter(header).chain(body).boxed())
+}
+
+fn choices(ballot: &Ballot, texts: &HashMap<ObjectId, String>) -> Vec<Choice> {
+    let choice = |option_id: &ObjectId, value: i64| Choice {
+        option_id: *option_id,
+        option_text: texts.get(option_id).cloned().unwrap_or_default(),
+        value,
+    };
+    match ballot {
+        Ballot::Single { option_id } => vec![choice(option_id, 1)],
+        Ballot::Approval { option_ids } => option_ids.iter().map(|id| choice(id, 1)).collect(),
+        Ballot::Ranked { rankings } => rankings
+            .iter()
+            .zip(1..)
+            .map(|(id, rank)| choice(id, rank))
+            .collect(),
+        Ballot::Rating { scores } => scores
+            .iter()
+            .map(|score| choice(&score.option_id, score.score as i64))
+            .collect(),
+        Ballot::Quadratic { allocations } => allocations
+            .iter()
+            .map(|allocation| choice(&allocation.option_id, allocation.votes as i64))
+            .collect(),
+    }
+}
+
+fn encode_ballot(ballot: &ExportedBallot, format: ExportFormat) -> Result<Bytes> {
+    match format {
+        ExportFormat::Ndjson => json_line(ballot),
+        ExportFormat::Csv => {
+            let rows: String = ballot
+                .choices
+                .iter()
+                .map(|choice| {
+                    csv_row(&[
+                        ballot.ballot.to_string(),
+                        timestamp(ballot.cast_at),
+                        timestamp(ballot.changed_at),
+                        choice.option_id.to_hex(),
+                        choice.option_text.clone(),
+                        choice.value.to_string(),
+                    ])
+                })
+                .collect();
+            Ok(Bytes::from(rows))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn option_text_is_quoted_only_when_it_has_to_be() {
+        assert_eq!(csv_field("Pizza"), "Pizza");
+        assert_eq!(csv_field("Salt, pepper"), "\"Salt, pepper\"");
+        assert_eq!(csv_field("The \"best\" one"), "\"The \"\"best\"\" one\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_row(&["a", "b,c", ""]), "a,\"b,c\",\r\n");
+    }
+
+    #[test]
+    fn ranked_ballots_export_one_row_per_preference() {
+        let options: Vec<ObjectId> = (0..2).map(|_| ObjectId::new()).collect();
+        let texts = HashMap::from([
+            (options[0], "Tea, hot".to_string()),
+            (options[1], "Coffee".to_string()),
+        ]);
+        let ballot = ExportedBallot {
+            ballot: 1,
+            cast_at: None,
+            changed_at: None,
+            choices: choices(
+                &Ballot::Ranked {
+                    rankings: vec![options[1], options[0]],
+                },
+                &texts,
+            ),
+        };
+        let csv = encode_ballot(&ballot, ExportFormat::Csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv.to_vec()).unwrap(),
+            format!(
+                "1,,,{},Coffee,1\r\n1,,,{},\"Tea, hot\",2\r\n",
+                options[1].to_hex(),
+                options[0].to_hex()
+            )
+        );
+        let json = encode_ballot(&ballot, ExportFormat::Ndjson).unwrap();
+        assert!(json.ends_with(b"\n") && !json[..json.len() - 1].contains(&b'\n'));
+    }
+}
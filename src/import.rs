@@ -0,0 +1,158 @@
+use serde::Serialize;
+
+use crate::models::poll_api_model::{NewPollRequest, OptionRequest};
+
+// one transaction has to hold every poll and option of an import
+pub const MAX_IMPORT_ROWS: usize = 200;
+
+/// A poll an import created, or would create on a dry run.
+#[derive(Serialize, Debug)]
+pub struct ImportedPoll {
+    pub row: usize,
+    pub id: String,
+    pub title: String,
+    pub options: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RowError {
+    pub row: usize,
+    pub error: String,
+}
+
+/// Outcome of an import. Polls are only created when no row has an error.
+#[derive(Serialize, Debug)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub created: usize,
+    pub polls: Vec<ImportedPoll>,
+    pub errors: Vec<RowError>,
+}
+
+/// Splits a JSON array of `NewPollRequest`s into rows, a row that doesn't
+/// match the request shape is reported on its own.
+pub fn parse_json(body: &[u8]) -> Result<Vec<Result<NewPollRequest, String>>, String> {
+    let rows: Vec<serde_json::Value> = serde_json::from_slice(body)
+        .map_err(|e| format!("Expected a JSON array of polls: {}", e))?;
+    Ok(rows
+        .into_iter()
+        .map(|row| serde_json::from_value(row).map_err(|e| e.to_string()))
+        .collect())
+}
+
+/// One poll per CSV record, the title first and its options after it. A
+/// leading `title,...` header is skipped, blank option cells are ignored and
+/// everything else takes the defaults of `NewPollRequest`.
+pub fn parse_csv(body: &str) -> Result<Vec<Result<NewPollRequest, String>>, String> {
+    let mut records = csv_records(body)?;
+    if records
+        .first()
+        .and_then(|record| record.first())
+        .is_some_and(|cell| cell.trim().eq_ignore_ascii_case("title"))
+    {
+        records.remove(0);
+    }
+    Ok(records
+        .into_iter()
+        .map(|record| {
+            let mut cells = record.into_iter();
+            let title = cells.next().unwrap_or_default().trim().to_string();
+            let options = cells
+                .map(|cell| cell.trim().to_string())
+                .filter(|text| !text.is_empty())
+                .map(|text| OptionRequest { text })
+                .collect();
+            Ok(NewPollRequest {
+                title,
+                options,
+                ..Default::default()
+            })
+        })
+        .collect())
+}
+
+/// RFC 4180 records, quoted cells may hold commas, doubled quotes and line
+/// breaks. Blank lines are skipped.
+fn csv_records(body: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = body.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if cell.is_empty() => quoted = true,
+            (',', false) => record.push(std::mem::take(&mut cell)),
+            ('\r', false) if chars.peek() == Some(&'\n') => (),
+            ('\n' | '\r', false) => {
+                record.push(std::mem::take(&mut cell));
+                if record.iter().any(|cell| !cell.is_empty()) {
+                    records.push(std::mem::take(&mut record));
+                }
+                record.clear();
+            }
+            (c, _) => cell.push(c),
+        }
+    }
+    if quoted {
+        return Err("CSV ends inside a quoted cell!".to_string());
+    }
+    record.push(cell);
+    if record.iter().any(|cell| !cell.is_empty()) {
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_rows_become_polls() {
+        let body = "Title,Option 1,Option 2\r\n\
+                    Lunch,\"Salt, pepper\",\"The \"\"usual\"\"\"\r\n\
+                    \r\n\
+                    \"Two\nlines\",Yes,No,\n\
+                    Empty,";
+        let rows: Vec<NewPollRequest> = parse_csv(body)
+            .unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        let polls: Vec<(&str, Vec<&str>)> = rows
+            .iter()
+            .map(|row| {
+                let options = row.options.iter().map(|o| o.text.as_str()).collect();
+                (row.title.as_str(), options)
+            })
+            .collect();
+        assert_eq!(
+            polls,
+            [
+                ("Lunch", vec!["Salt, pepper", "The \"usual\""]),
+                ("Two\nlines", vec!["Yes", "No"]),
+                ("Empty", vec![]),
+            ]
+        );
+        assert!(parse_csv("Lunch,\"open").is_err());
+    }
+
+    #[test]
+    fn json_rows_fail_on_their_own() {
+        let body = br#"[
+            {"title": "Lunch", "options": [{"text": "Pizza"}, {"text": "Sushi"}]},
+            {"title": "Broken"}
+        ]"#;
+        let rows = parse_json(body).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].is_ok());
+        assert!(rows[1].is_err());
+        assert!(parse_json(b"{}").is_err());
+    }
+}
@@ -11,15 +11,21 @@ use middlewares::authenticate::authenticate_user;
 use routes::{auth_routes, general_routes, poll_routes, sse_route};
 use serde_json::json;
 use sse::Broadcaster;
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use utils::jwt::JWT;
 use webauthn::config_webauthn;
 pub mod config;
 pub mod db;
+pub mod errors;
+pub mod export;
+pub mod import;
+pub mod maintenance;
 pub mod middlewares;
 pub mod models;
 pub mod routes;
+pub mod scheduler;
 pub mod sse;
+pub mod tabulation;
 pub mod utils;
 pub mod webauthn;
 
@@ -36,9 +42,23 @@ async fn main() -> Result<(), std::io::Error> {
     let app_configs = Arc::new(AppConfig::init());
     let client_origin = app_configs.client_origin.clone();
     let mongodb = Data::new(DB::init(app_configs.clone()).await.unwrap());
+    // `polling-app-backend purge-orphans [--dry-run]` runs maintenance instead of the server
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("purge-orphans") {
+        let dry_run = args.iter().any(|arg| arg == "--dry-run");
+        return maintenance::purge_orphans(&mongodb, dry_run)
+            .await
+            .map_err(std::io::Error::other);
+    }
     let webauthn = Data::new(config_webauthn(app_configs.clone()).unwrap());
     let jwt = Data::new(JWT::init());
     let broadcaster = Broadcaster::create();
+    actix_web::rt::spawn(scheduler::run_poll_schedule(
+        mongodb.clone(),
+        broadcaster.clone(),
+        Duration::from_secs(app_configs.poll_schedule_interval_secs),
+        Duration::from_secs(app_configs.trash_retention_days * 24 * 60 * 60),
+    ));
     HttpServer::new(move || {
         App::new()
             .wrap(
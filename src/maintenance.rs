@@ -0,0 +1,93 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Document},
+    Collection,
+};
+
+use crate::db::DB;
+
+// options younger than this may belong to a poll that is still being created
+const ORPHAN_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+/// Ids of the polls that documents in `collection` point to but that no
+/// longer exist, and how many documents they left behind.
+async fn find_orphaned_polls<T: Send + Sync>(
+    collection: &Collection<T>,
+) -> Result<(Vec<String>, u64)> {
+    let pipeline = vec![
+        doc! {"$group": {"_id": "$poll_id", "count": {"$sum": 1}}},
+        doc! {
+            "$lookup": {
+                "from": "polls",
+                "localField": "_id",
+                "foreignField": "id",
+                "as": "poll"
+            }
+        },
+        doc! {"$match": {"poll": {"$size": 0}}},
+        doc! {"$project": {"_id": 1, "count": {"$toLong": "$count"}}},
+    ];
+    let orphans: Vec<Document> = collection.aggregate(pipeline).await?.try_collect().await?;
+    let poll_ids = orphans
+        .iter()
+        .filter_map(|orphan| orphan.get_str("_id").ok())
+        .map(str::to_string)
+        .collect();
+    let count = orphans
+        .iter()
+        .filter_map(|orphan| orphan.get_i64("count").ok())
+        .map(|count| count as u64)
+        .sum();
+    Ok((poll_ids, count))
+}
+
+async fn delete_by_polls<T: Send + Sync>(
+    collection: &Collection<T>,
+    poll_ids: &[String],
+) -> Result<u64> {
+    let result = collection
+        .delete_many(doc! {"poll_id": {"$in": poll_ids}})
+        .await?;
+    Ok(result.deleted_count)
+}
+
+/// Removes options, votes, invites, secret ballots and receipts whose poll
+/// no longer exists, left behind by deletions from before they cascaded.
+/// With `dry_run` only reports them.
+pub async fn purge_orphans(db: &DB, dry_run: bool) -> Result<()> {
+    let options = db
+        .options
+        .find_orphans(SystemTime::now() - ORPHAN_GRACE_PERIOD)
+        .await?;
+    let votes = db.votes.find_orphans().await?;
+    let (invite_polls, invites) = find_orphaned_polls(&db.invites.collection).await?;
+    let (secret_ballot_polls, secret_ballots) =
+        find_orphaned_polls(&db.secret_ballots.collection).await?;
+    let (receipt_polls, receipts) = find_orphaned_polls(&db.receipts.collection).await?;
+    println!(
+        "Found {} orphaned options, {} votes, {} invites, {} secret ballot buckets and {} receipts",
+        options.len(),
+        votes.len(),
+        invites,
+        secret_ballots,
+        receipts
+    );
+    if dry_run {
+        return Ok(());
+    }
+
+    let deleted_options = db.options.delete_many(&options).await?;
+    let deleted_votes = db.votes.delete_many(&votes).await?;
+    let deleted_invites = delete_by_polls(&db.invites.collection, &invite_polls).await?;
+    let deleted_secret_ballots =
+        delete_by_polls(&db.secret_ballots.collection, &secret_ballot_polls).await?;
+    let deleted_receipts = delete_by_polls(&db.receipts.collection, &receipt_polls).await?;
+    println!(
+        "Purged {} options, {} votes, {} invites, {} secret ballot buckets and {} receipts",
+        deleted_options, deleted_votes, deleted_invites, deleted_secret_ballots, deleted_receipts
+    );
+    Ok(())
+}
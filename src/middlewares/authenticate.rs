@@ -1,15 +1,44 @@
+use std::future::{ready, Ready};
+
+use crate::db::DB;
 use crate::utils::jwt::JWT;
 use actix_web::body::BoxBody;
 use actix_web::{
-    dev::{ServiceRequest, ServiceResponse},
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    error::ErrorUnauthorized,
     http::StatusCode,
     middleware::Next,
     web::Data,
-    HttpResponse,
+    FromRequest, HttpMessage, HttpRequest, HttpResponse,
 };
-use log::info;
+use futures::future::join;
+use log::{debug, error, info};
 use serde_json::json;
 
+/// The user resolved from the `auth_token` cookie by [`authenticate_user`].
+/// Handlers behind the middleware take this as an argument instead of
+/// reading a username from the request body.
+#[derive(Clone, Debug)]
+pub struct AuthenticatedUser {
+    pub username: String,
+    pub uuid: String,
+    pub session_id: String,
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let user = req
+            .extensions()
+            .get::<AuthenticatedUser>()
+            .cloned()
+            .ok_or_else(|| ErrorUnauthorized("User not authenticated!"));
+        ready(user)
+    }
+}
+
 pub async fn authenticate_user(
     req: ServiceRequest,
     next: Next<BoxBody>,
@@ -19,23 +48,76 @@ pub async fn authenticate_user(
         .app_data::<Data<JWT>>()
         .expect("JWT not configured")
         .clone();
-    if let Some(cookie) = req.cookie("auth_token") {
-        let token = cookie.value();
+    let db = req
+        .app_data::<Data<DB>>()
+        .expect("DB not configured")
+        .clone();
+    let token = match req.cookie("auth_token") {
+        Some(cookie) => cookie.value().to_string(),
+        None => {
+            return Ok(req.into_response(
+                HttpResponse::BadRequest()
+                    .status(StatusCode::BAD_REQUEST)
+                    .json(json!({"msg":"Missing auth token cookie!","isAuthenticated":false})),
+            ));
+        }
+    };
 
-        if jwt.verify(token) {
-            return next.call(req).await;
-        } else {
+    let claims = match jwt.decode(&token) {
+        Ok(claims) => claims,
+        Err(e) => {
+            debug!("Rejected auth token {:?}", e);
             return Ok(req.into_response(
                 HttpResponse::Unauthorized()
                     .status(StatusCode::FORBIDDEN)
                     .json(json!({"msg":"Invalid or expired token!","isAuthenticated":false})),
             ));
         }
-    }
+    };
+
+    let (session_match, user_match) = join(
+        db.sessions.find_active(&claims.sid),
+        db.users.search_by_uuid(&claims.uuid),
+    )
+    .await;
+    let session = match session_match {
+        Ok(Some(session)) => session,
+        Ok(None) => {
+            return Ok(req.into_response(
+                HttpResponse::Unauthorized()
+                    .json(json!({"msg":"Session expired or revoked!","isAuthenticated":false})),
+            ));
+        }
+        Err(e) => {
+            error!("Error resolving session {:?}", e);
+            return Ok(req.into_response(
+                HttpResponse::InternalServerError()
+                    .json(json!({"msg":"Something went wrong!","isAuthenticated":false})),
+            ));
+        }
+    };
+    let user = match user_match {
+        Ok(Some(user)) if user.username == session.username => user,
+        Ok(_) => {
+            return Ok(req.into_response(
+                HttpResponse::Unauthorized()
+                    .status(StatusCode::FORBIDDEN)
+                    .json(json!({"msg":"User no longer exists!","isAuthenticated":false})),
+            ));
+        }
+        Err(e) => {
+            error!("Error resolving authenticated user {:?}", e);
+            return Ok(req.into_response(
+                HttpResponse::InternalServerError()
+                    .json(json!({"msg":"Something went wrong!","isAuthenticated":false})),
+            ));
+        }
+    };
 
-    return Ok(req.into_response(
-        HttpResponse::BadRequest()
-            .status(StatusCode::BAD_REQUEST)
-            .json(json!({"msg":"Missing auth token cookie!","isAuthenticated":false})),
-    ));
+    req.extensions_mut().insert(AuthenticatedUser {
+        username: user.username,
+        uuid: user.uuid,
+        session_id: claims.sid,
+    });
+    next.call(req).await
 }
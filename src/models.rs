@@ -1,2 +1,2 @@
+pub mod auth_api_model;
 pub mod poll_api_model;
-
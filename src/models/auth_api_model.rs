@@ -0,0 +1,42 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{credentials_repo::Credential, sessions_repo::Session};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CredentialResponse {
+    pub id: String,
+    pub nickname: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl From<Credential> for CredentialResponse {
+    fn from(credential: Credential) -> Self {
+        CredentialResponse {
+            id: credential.id.to_hex(),
+            nickname: credential.nickname,
+            created_at: credential.created_at,
+            last_used_at: credential.last_used_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SessionResponse {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub last_refreshed_at: DateTime<Utc>,
+    pub current: bool,
+}
+
+impl SessionResponse {
+    pub fn from_session(session: Session, current_session_id: &str) -> Self {
+        SessionResponse {
+            current: session.session_id == current_session_id,
+            id: session.session_id,
+            created_at: session.created_at,
+            last_refreshed_at: session.last_refreshed_at,
+        }
+    }
+}
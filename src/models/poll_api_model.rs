@@ -1,13 +1,44 @@
+use std::collections::HashMap;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::db::options_repo::OptionModel;
+use mongodb::bson::oid::ObjectId;
+use nanoid::nanoid;
 
-#[derive(Deserialize, Serialize, Debug)]
+use crate::{
+    db::{
+        options_repo::OptionModel,
+        polls_repo::{
+            Poll, PollEdit, PollType, ResultsVisibility, Visibility, SECRET_BALLOT_RESULTS,
+        },
+        receipts_repo::{ChainEntry, ChainHead},
+    },
+    export::{ExportData, ExportFormat},
+    tabulation::{
+        instant_runoff::Runoff, quadratic::QuadraticTally, rating::RatingSummary, schulze::Schulze,
+    },
+};
+
+#[derive(Deserialize, Serialize, Debug, Default)]
 pub struct NewPollRequest {
     pub title: String,
+    pub description: Option<String>,
     pub options: Vec<OptionRequest>,
-    pub ownername: String,
+    #[serde(default)]
+    pub poll_type: PollType,
+    #[serde(default)]
+    pub allow_vote_change: bool,
+    // store ballots apart from who cast them, can't be combined with `allow_vote_change`
+    #[serde(default)]
+    pub secret_ballot: bool,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub results_visibility: ResultsVisibility,
+    // optional voting window, the poll opens right away without `opens_at`
+    pub opens_at: Option<DateTime<Utc>>,
+    pub closes_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -15,39 +46,229 @@ pub struct OptionRequest {
     pub text: String,
 }
 
+impl NewPollRequest {
+    /// Validates the request and builds the poll owned by `owner_id` along
+    /// with its options, nothing is written yet.
+    pub fn into_poll(self, owner_id: &str) -> Result<(Poll, Vec<OptionModel>), String> {
+        if self.title.trim().is_empty() {
+            return Err("Poll needs a title!".to_string());
+        }
+        if self.options.len() < 2 {
+            return Err("Minimum two options are needed!".to_string());
+        }
+        self.poll_type.check(self.options.len())?;
+        if self.secret_ballot && self.allow_vote_change {
+            return Err("Secret ballots can't be changed once cast!".to_string());
+        }
+        if self.secret_ballot && !self.results_visibility.hides_live_counts() {
+            return Err(SECRET_BALLOT_RESULTS.to_string());
+        }
+        let now = Utc::now();
+        let opens_at = self.opens_at.filter(|opens_at| *opens_at > now);
+        if let Some(closes_at) = self.closes_at {
+            if closes_at <= opens_at.unwrap_or(now) {
+                return Err("Poll must close after it opens!".to_string());
+            }
+        }
+
+        let options: Vec<OptionModel> = self
+            .options
+            .into_iter()
+            .map(|option| OptionModel {
+                _id: ObjectId::new(),
+                text: option.text,
+                votes_count: 0,
+            })
+            .collect();
+        let poll = Poll {
+            id: nanoid!(),
+            created_at: now,
+            updated_at: now,
+            title: self.title,
+            description: self.description.filter(|d| !d.trim().is_empty()),
+            options: options.iter().map(|option| option._id).collect(),
+            owner_id: owner_id.to_string(),
+            // scheduled polls are opened by the scheduler
+            is_open: opens_at.is_none(),
+            poll_type: self.poll_type,
+            allow_vote_change: self.allow_vote_change,
+            secret_ballot: self.secret_ballot,
+            visibility: self.visibility,
+            results_visibility: self.results_visibility,
+            access_code: None,
+            opens_at,
+            closes_at: self.closes_at,
+            closed_at: None,
+            deleted_at: None,
+            history: Vec::new(),
+            chain_head: None,
+            final_digest: None,
+        };
+        Ok((poll, options))
+    }
+}
+
+/// Body of `PATCH /polls/{id}`. An empty `description` clears it, options
+/// with votes are only removed when `discard_votes` is set.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct EditPollRequest {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub visibility: Option<Visibility>,
+    pub results_visibility: Option<ResultsVisibility>,
+    #[serde(default)]
+    pub add_options: Vec<OptionRequest>,
+    #[serde(default)]
+    pub remove_options: Vec<String>,
+    #[serde(default)]
+    pub discard_votes: bool,
+}
+
+/// Query of requests that open an invite-only poll, either a personal
+/// `invite` token or the poll's shared access `code`.
+#[derive(Deserialize, Debug)]
+pub struct PollAccessParams {
+    pub invite: Option<String>,
+    pub code: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ImportParams {
+    // only validate and report what would be created
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ExportParams {
+    #[serde(default)]
+    pub format: ExportFormat,
+    #[serde(default)]
+    pub data: ExportData,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NewInviteRequest {
+    pub invitee: Option<String>,
+}
+
+/// Body of a vote. Single choice polls take `optionId`, approval polls
+/// `optionIds`, ranked polls `rankings` (most preferred first), rating
+/// polls `scores` and quadratic polls `allocations`, both keyed by option id.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CastVoteRequest {
+    pub option_id: Option<String>,
+    pub option_ids: Option<Vec<String>>,
+    pub rankings: Option<Vec<String>>,
+    pub scores: Option<HashMap<String, i32>>,
+    pub allocations: Option<HashMap<String, u32>>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PollOptionResult {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
     pub text: String,
     pub votes_count: i64,
     pub votes_percentage: f64,
+    // score statistics, rating polls only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<RatingSummary>,
+    // raw votes and credits spent, quadratic polls only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quadratic: Option<QuadraticTally>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PollResults {
     pub id: String,
     pub title: String,
+    pub poll_type: PollType,
+    // number of voters; an approval ballot counts once however many options it picks
     pub total_votes: i64,
     pub options: Vec<PollOptionResult>,
+    // round by round instant runoff, ranked polls only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runoff: Option<Runoff>,
+    // condorcet ordering of the same ballots, ranked polls only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schulze: Option<Schulze>,
+}
+
+/// An option as shown to voters, without `votes_count` while the results are hidden from them.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OptionView {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub votes_count: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GetPollResponse {
     pub id: String,
     pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
     pub owner_id: String,
-    pub options: Vec<OptionModel>,
+    pub options: Vec<OptionView>,
     pub total_votes: i64,
     pub is_open: bool,
-    #[serde(skip_serializing)]
-    pub voters: Vec<String>,
+    #[serde(default)]
+    pub poll_type: PollType,
+    #[serde(default)]
+    pub allow_vote_change: bool,
+    #[serde(default)]
+    pub secret_ballot: bool,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub results_visibility: ResultsVisibility,
+    #[serde(
+        default,
+        deserialize_with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional::deserialize"
+    )]
+    pub opens_at: Option<DateTime<Utc>>,
+    #[serde(
+        default,
+        deserialize_with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional::deserialize"
+    )]
+    pub closes_at: Option<DateTime<Utc>>,
     #[serde(default = "Utc::now")]
     pub created_at: DateTime<Utc>,
     #[serde(default = "Utc::now")]
     pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub history: Vec<PollEdit>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PollResponse {
     pub poll: Option<GetPollResponse>,
     pub has_voted: bool,
+    // false while the poll's `results_visibility` keeps counts from the caller
+    pub results_visible: bool,
+    // the poll was edited after the caller last cast or changed their vote
+    pub edited_since_vote: bool,
+}
+
+/// Public state of a poll's vote log.
+#[derive(Serialize, Debug)]
+pub struct VoteLogResponse {
+    pub poll_id: String,
+    pub head: Option<ChainHead>,
+    pub final_digest: Option<String>,
+    // None until the poll is sealed, false once the counters no longer match the seal
+    pub counts_match: Option<bool>,
+}
+
+/// Entries from a receipt up to the current head. Recomputing each hash from
+/// `entry` on and landing on `head` proves the receipt is in the log unchanged.
+#[derive(Serialize, Debug)]
+pub struct ReceiptProofResponse {
+    pub entry: ChainEntry,
+    pub path: Vec<ChainEntry>,
+    pub head: Option<ChainHead>,
 }
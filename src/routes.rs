@@ -1,4 +1,6 @@
 pub mod auth_routes;
+pub mod credential_routes;
 pub mod general_routes;
 pub mod poll_routes;
+pub mod session_routes;
 pub mod sse_route;
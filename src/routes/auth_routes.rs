@@ -1,42 +1,59 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
+use std::collections::HashMap;
 
 use actix_web::{
     cookie::{time::Duration, Cookie, SameSite},
     http::StatusCode,
-    web::{Data, Json, Path, ServiceConfig},
-    HttpResponse, Responder,
+    middleware::from_fn,
+    web::{scope, Data, Json, Path, ServiceConfig},
+    HttpRequest, HttpResponse, Responder,
 };
-use log::error;
-use mongodb::bson::oid::ObjectId;
+use chrono::Utc;
+use log::{error, warn};
+use mongodb::bson::{self, oid::ObjectId};
+use nanoid::nanoid;
+use serde_json::json;
 use webauthn_rs::{
     prelude::{
-        Passkey, PasskeyAuthentication, PasskeyRegistration, PublicKeyCredential,
+        AuthenticationResult, DiscoverableAuthentication, DiscoverableKey, Passkey,
+        PasskeyAuthentication, PasskeyRegistration, PublicKeyCredential,
         RegisterPublicKeyCredential, Uuid,
     },
     Webauthn,
 };
 
 use crate::{
-    db::{auth_state_repo::AuthState, reg_state_repo::RegState, users_repo::User, DB},
-    utils::{json_responder::Response, jwt::JWT},
+    db::{
+        auth_state_repo::{AuthState, DiscoverableAuthState},
+        credentials_repo::{encode_cred_id, Credential},
+        reg_state_repo::RegState,
+        users_repo::User,
+        DB,
+    },
+    middlewares::authenticate::authenticate_user,
+    routes::{credential_routes, session_routes},
+    utils::{
+        json_responder::Response,
+        jwt::{ACCESS_TOKEN_TTL_MINUTES, JWT, REFRESH_TOKEN_TTL_DAYS},
+    },
+    webauthn::require_resident_key,
 };
 
 #[actix_web::post("/register/start")]
 pub async fn start_registration(
-    db: Data<Arc<Mutex<DB>>>,
+    db: Data<DB>,
     webauthn: Data<Webauthn>,
     Json(req): Json<HashMap<String, String>>,
 ) -> impl Responder {
-    let db = db.lock().unwrap();
     let username = match req.get("username") {
         Some(username) => username,
         None => {
             return Response::<()>::error("No username found", StatusCode::BAD_REQUEST);
         }
     };
+    // abandoned registrations must not keep holding on to usernames
+    if let Err(e) = db.reg_states.delete_expired().await {
+        error!("Error releasing expired registrations {:?}", e);
+    }
     let users_match = db.users.search_by_username(username).await;
 
     let uuid = match users_match {
@@ -53,17 +70,18 @@ pub async fn start_registration(
         }
     };
 
-    let (ccr, reg_state) = match webauthn.start_passkey_registration(uuid, username, username, None)
-    {
-        Ok(data) => data,
-        Err(e) => {
-            error!("Error creating challange and reg state {}", e);
-            return Response::<()>::error(
-                "Something went wrong!",
-                StatusCode::INTERNAL_SERVER_ERROR,
-            );
-        }
-    };
+    let (mut ccr, reg_state) =
+        match webauthn.start_passkey_registration(uuid, username, username, None) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Error creating challange and reg state {}", e);
+                return Response::<()>::error(
+                    "Something went wrong!",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                );
+            }
+        };
+    require_resident_key(&mut ccr);
     // serialize the reg state
     let reg_state = match serde_json::to_value(&reg_state) {
         Ok(value) => value,
@@ -80,6 +98,7 @@ pub async fn start_registration(
         username: username.to_string(),
         uuid: uuid.to_string(),
         reg_state,
+        created_at: bson::DateTime::now(),
     };
 
     let result = match db.reg_states.insert(new_user_reg_state).await {
@@ -94,20 +113,19 @@ pub async fn start_registration(
 
 #[actix_web::post("/register/finish/{username}")]
 pub async fn finish_registration(
-    db: Data<Arc<Mutex<DB>>>,
+    db: Data<DB>,
     webauthn: Data<Webauthn>,
     username: Path<String>,
     request: Json<RegisterPublicKeyCredential>,
 ) -> impl Responder {
-    let db = db.lock().unwrap();
     let username = username.as_str();
-    let _does_reg_state_exist = match db.reg_states.is_exists(username).await {
+    let _does_reg_state_exist = match db.reg_states.is_live(username).await {
         Ok(data) => {
             if data {
                 data
             } else {
                 return Response::<String>::error(
-                    "No registration init found!",
+                    "No registration init found or it has expired!",
                     StatusCode::BAD_REQUEST,
                 );
             }
@@ -122,8 +140,8 @@ pub async fn finish_registration(
     };
 
     let reg_state_match = db.reg_states.find_by_username(username).await;
-    let reg_state_match = if let Ok(Some(doc_match)) = reg_state_match {
-        doc_match.reg_state
+    let (user_uuid, reg_state_match) = if let Ok(Some(doc_match)) = reg_state_match {
+        (doc_match.uuid, doc_match.reg_state)
     } else {
         eprintln!("Error at register finish!");
         return Response::<String>::error(
@@ -165,6 +183,7 @@ pub async fn finish_registration(
     };
 
     // serialize the key
+    let cred_id = encode_cred_id(pass_key.cred_id());
     let sk = match serde_json::to_value(pass_key) {
         Ok(data) => data,
         Err(e) => {
@@ -176,11 +195,11 @@ pub async fn finish_registration(
         }
     };
 
+    // the uuid must match the user handle the passkey was registered with
     let new_user = User {
         id: Some(ObjectId::new()),
         username: username.to_string(),
-        uuid: Uuid::new_v4().to_string(),
-        sk,
+        uuid: user_uuid,
     };
 
     let result = match db.users.insert(new_user).await {
@@ -194,6 +213,24 @@ pub async fn finish_registration(
         }
     };
 
+    let new_credential = Credential {
+        id: ObjectId::new(),
+        username: username.to_string(),
+        cred_id,
+        nickname: "Passkey 1".to_string(),
+        passkey: sk,
+        created_at: Utc::now(),
+        last_used_at: None,
+    };
+
+    if let Err(e) = db.credentials.insert(new_credential).await {
+        error!("Failed storing the passkey of {} {:?}", username, e);
+        return Response::<String>::error(
+            "Failed registering user!",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        );
+    }
+
     let _del_reg_state = match db.reg_states.delete_by_username(username).await {
         Ok(response) => response,
         Err(e) => {
@@ -210,11 +247,10 @@ pub async fn finish_registration(
 
 #[actix_web::post("/login/start")]
 pub async fn start_authentication(
-    db: Data<Arc<Mutex<DB>>>,
+    db: Data<DB>,
     webauthn: Data<Webauthn>,
     Json(req): Json<HashMap<String, String>>,
 ) -> impl Responder {
-    let db = db.lock().unwrap();
     let username = match req.get("username") {
         Some(username) => username,
         None => {
@@ -238,20 +274,23 @@ pub async fn start_authentication(
         }
     };
 
-    let user_sk = db.users.search_by_username(username).await;
-    let user_sk = if let Ok(Some(user)) = user_sk {
-        user.sk
-    } else {
-        eprintln!("Error fetching user!");
-        return Response::<String>::error(
-            "Error authenticating user!",
-            StatusCode::INTERNAL_SERVER_ERROR,
-        );
+    let credentials = match db.credentials.find_by_username(username).await {
+        Ok(credentials) => credentials,
+        Err(e) => {
+            error!("Error fetching credentials of {} {:?}", username, e);
+            return Response::<String>::error(
+                "Error authenticating user!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
     };
 
-    // for now just a single passkey
-    let sk: Vec<Passkey> = match serde_json::from_value(user_sk) {
-        Ok(key) => vec![key],
+    let sk: Vec<Passkey> = match credentials
+        .iter()
+        .map(|credential| credential.to_passkey())
+        .collect()
+    {
+        Ok(keys) => keys,
         Err(e) => {
             error!("Error deserializing sk {:?}", e);
             return Response::<String>::error(
@@ -261,6 +300,10 @@ pub async fn start_authentication(
         }
     };
 
+    if sk.is_empty() {
+        return Response::<String>::error("No passkeys registered!", StatusCode::BAD_REQUEST);
+    }
+
     let (rcr, auth_state) = match webauthn.start_passkey_authentication(&sk) {
         Ok(data) => data,
         Err(e) => {
@@ -286,6 +329,7 @@ pub async fn start_authentication(
     let auth_state_entry = AuthState {
         auth_state: serial_auth_state,
         username: username.to_string(),
+        created_at: bson::DateTime::now(),
     };
 
     let _result = match db.auth_states.insert(auth_state_entry).await {
@@ -298,25 +342,27 @@ pub async fn start_authentication(
             );
         }
     };
-    return Response::ok(rcr, StatusCode::OK);
+    Response::ok(rcr, StatusCode::OK)
 }
 
 #[actix_web::post("/login/finish/{username}")]
 pub async fn finish_authentication(
     username: Path<String>,
-    db: Data<Arc<Mutex<DB>>>,
+    db: Data<DB>,
     webauthn: Data<Webauthn>,
     req: Json<PublicKeyCredential>,
     jwt: Data<JWT>,
 ) -> impl Responder {
-    let db = db.lock().unwrap();
     let username = username.as_str();
-    let _does_auth_state_exist = match db.auth_states.is_exists(username).await {
+    let _does_auth_state_exist = match db.auth_states.is_live(username).await {
         Ok(data) => {
             if data {
                 data
             } else {
-                return Response::<String>::error("No user found!", StatusCode::BAD_REQUEST);
+                return Response::<String>::error(
+                    "No login init found or it has expired!",
+                    StatusCode::BAD_REQUEST,
+                );
             }
         }
         Err(e) => {
@@ -349,7 +395,7 @@ pub async fn finish_authentication(
         }
     };
 
-    let _auth_result = match webauthn.finish_passkey_authentication(&req, &deserialized_as) {
+    let auth_result = match webauthn.finish_passkey_authentication(&req, &deserialized_as) {
         Ok(result) => result,
         Err(e) => {
             error!("Error authenticating {:?}", e);
@@ -371,13 +417,250 @@ pub async fn finish_authentication(
         }
     };
 
+    let cred_id = encode_cred_id(auth_result.cred_id());
+    let credential = match db.credentials.find_by_cred_id(&cred_id).await {
+        Ok(Some(credential)) if credential.username == username => credential,
+        Ok(_) => {
+            warn!("Login for {} used a credential it does not own", username);
+            return Response::<String>::error("Unknown credential!", StatusCode::FORBIDDEN);
+        }
+        Err(e) => {
+            error!("Error fetching credential {:?}", e);
+            return Response::<String>::error(
+                "Something went wrong!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+    record_credential_use(&db, &credential, &auth_result).await;
+
     let uuid = match db.users.search_by_username(username).await.unwrap() {
         Some(user) => user.uuid,
         None => "1".to_string(),
     };
 
-    let jwt_token = match jwt.sign(uuid) {
-        Ok(token) => token,
+    login_response(&db, &jwt, uuid, username).await
+}
+
+#[actix_web::post("/login/discoverable/start")]
+pub async fn start_discoverable_authentication(
+    db: Data<DB>,
+    webauthn: Data<Webauthn>,
+) -> impl Responder {
+    let (rcr, auth_state) = match webauthn.start_discoverable_authentication() {
+        Ok(data) => data,
+        Err(e) => {
+            error!("Error generating discoverable auth challange {:?}", e);
+            return Response::<String>::error(
+                "Something went wrong!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+
+    let serial_auth_state = match serde_json::to_value(auth_state) {
+        Ok(serial_auth_state) => serial_auth_state,
+        Err(e) => {
+            error!("Error serialzing auth state {:?}", e);
+            return Response::<String>::error(
+                "Something went wrong!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+
+    let challenge_id = nanoid!();
+    let auth_state_entry = DiscoverableAuthState {
+        challenge_id: challenge_id.clone(),
+        auth_state: serial_auth_state,
+        created_at: bson::DateTime::now(),
+    };
+
+    if let Err(e) = db.auth_states.insert_discoverable(auth_state_entry).await {
+        error!("Error writing discoverable auth state to db {:?}", e);
+        return Response::<String>::error(
+            "Something went wrong!",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        );
+    }
+
+    Response::ok(
+        json!({
+            "challenge_id": challenge_id,
+            "options": rcr,
+        }),
+        StatusCode::OK,
+    )
+}
+
+#[actix_web::post("/login/discoverable/finish/{challenge_id}")]
+pub async fn finish_discoverable_authentication(
+    challenge_id: Path<String>,
+    db: Data<DB>,
+    webauthn: Data<Webauthn>,
+    req: Json<PublicKeyCredential>,
+    jwt: Data<JWT>,
+) -> impl Responder {
+    let auth_state = match db
+        .auth_states
+        .take_discoverable(challenge_id.as_str())
+        .await
+    {
+        Ok(Some(data)) => data.auth_state,
+        Ok(None) => {
+            return Response::<String>::error(
+                "No login init found or it has expired!",
+                StatusCode::BAD_REQUEST,
+            );
+        }
+        Err(e) => {
+            error!("Error fetching discoverable auth state {:?}", e);
+            return Response::<String>::error(
+                "Something went wrong!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+
+    let deserialized_as: DiscoverableAuthentication = match serde_json::from_value(auth_state) {
+        Ok(data) => data,
+        Err(e) => {
+            error!("Error deserialzing auth state {}", e);
+            return Response::<String>::error(
+                "Something went wrong!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+
+    // the credential id tells us whose passkey the browser picked
+    let (user_handle, cred_id) = match webauthn.identify_discoverable_authentication(&req) {
+        Ok((user_handle, cred_id)) => (user_handle, encode_cred_id(cred_id)),
+        Err(e) => {
+            error!("Error identifying discoverable credential {:?}", e);
+            return Response::<String>::error("Unknown credential!", StatusCode::BAD_REQUEST);
+        }
+    };
+
+    let credential = match db.credentials.find_by_cred_id(&cred_id).await {
+        Ok(Some(credential)) => credential,
+        Ok(None) => {
+            return Response::<String>::error("Unknown credential!", StatusCode::BAD_REQUEST);
+        }
+        Err(e) => {
+            error!("Error fetching credential {:?}", e);
+            return Response::<String>::error(
+                "Something went wrong!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+
+    let uuid = match db.users.search_by_username(&credential.username).await {
+        Ok(Some(user)) => user.uuid,
+        Ok(None) => {
+            return Response::<String>::error("No user found!", StatusCode::BAD_REQUEST);
+        }
+        Err(e) => {
+            error!("Error fetching user {:?}", e);
+            return Response::<String>::error(
+                "Something went wrong!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+
+    // the authenticator vouches for the account too, it has to be the credential's owner
+    if Uuid::parse_str(&uuid).ok() != Some(user_handle) {
+        error!(
+            "User handle of credential {} doesn't match {}",
+            cred_id, credential.username
+        );
+        return Response::<String>::error(
+            "Failed authenticating passkey!",
+            StatusCode::UNAUTHORIZED,
+        );
+    }
+
+    let discoverable_key = match credential.to_passkey() {
+        Ok(passkey) => DiscoverableKey::from(&passkey),
+        Err(e) => {
+            error!("Error deserializing credential {:?}", e);
+            return Response::<String>::error(
+                "Something went wrong!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+
+    let auth_result = match webauthn.finish_discoverable_authentication(
+        &req,
+        deserialized_as,
+        &[discoverable_key],
+    ) {
+        Ok(result) => result,
+        Err(e) => {
+            error!("Error authenticating {:?}", e);
+            return Response::<String>::error(
+                "Failed authenticating passkey!",
+                StatusCode::UNAUTHORIZED,
+            );
+        }
+    };
+
+    record_credential_use(&db, &credential, &auth_result).await;
+
+    login_response(&db, &jwt, uuid, &credential.username).await
+}
+
+// persist the signature counter so a cloned authenticator fails its next login
+async fn record_credential_use(
+    db: &DB,
+    credential: &Credential,
+    auth_result: &AuthenticationResult,
+) {
+    let updated_passkey = match credential.to_passkey() {
+        Ok(mut passkey) => match passkey.update_credential(auth_result) {
+            Some(true) => serde_json::to_value(&passkey).ok(),
+            _ => None,
+        },
+        Err(e) => {
+            error!("Error deserializing credential {:?}", e);
+            None
+        }
+    };
+    if let Err(e) = db
+        .credentials
+        .record_use(&credential.cred_id, updated_passkey)
+        .await
+    {
+        error!(
+            "Error updating credential of {} {:?}",
+            credential.username, e
+        );
+    }
+}
+
+async fn login_response(db: &DB, jwt: &JWT, uuid: String, username: &str) -> HttpResponse {
+    let session = match db.sessions.create(username).await {
+        Ok(session) => session,
+        Err(e) => {
+            error!("Error creating session for {} {:?}", username, e);
+            return Response::<String>::error(
+                "Something went wrong!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+
+    let tokens = jwt
+        .sign(uuid, session.session_id.clone())
+        .and_then(|access_token| {
+            jwt.sign_refresh(session.session_id, session.refresh_token_id)
+                .map(|refresh_token| (access_token, refresh_token))
+        });
+    let (access_token, refresh_token) = match tokens {
+        Ok(tokens) => tokens,
         Err(e) => {
             error!("Error generating the jwt token {} {}", username, e);
             return Response::<String>::error(
@@ -387,40 +670,191 @@ pub async fn finish_authentication(
         }
     };
 
-    let cookie = Cookie::build("auth_token", jwt_token)
+    HttpResponse::Ok()
+        .cookie(access_cookie(access_token))
+        .cookie(refresh_cookie(refresh_token))
+        .status(StatusCode::CREATED)
+        .json("User logged in!")
+}
+
+pub fn access_cookie(token: String) -> Cookie<'static> {
+    Cookie::build("auth_token", token)
         .http_only(true)
         .same_site(SameSite::None)
         .secure(true)
         .path("/")
-        .max_age(Duration::hours(1))
-        .finish();
-
-    return HttpResponse::Ok()
-        .cookie(cookie)
-        .status(StatusCode::CREATED)
-        .json("User logged in!");
+        .max_age(Duration::minutes(ACCESS_TOKEN_TTL_MINUTES))
+        .finish()
 }
 
-#[actix_web::get("/logout")]
-pub async fn logout_user() -> impl Responder {
-    let cookie = Cookie::build("auth_token", "")
+// only ever sent to the auth routes, the rest of the api never sees it
+pub fn refresh_cookie(token: String) -> Cookie<'static> {
+    Cookie::build("refresh_token", token)
         .http_only(true)
         .same_site(SameSite::None)
         .secure(true)
-        .path("/")
-        .max_age(Duration::days(-1))
-        .finish();
-    return HttpResponse::Ok()
-        .cookie(cookie)
-        .status(StatusCode::CREATED)
-        .json("User logged out!");
+        .path("/api/auth")
+        .max_age(Duration::days(REFRESH_TOKEN_TTL_DAYS))
+        .finish()
+}
+
+pub fn clear_session_cookies(mut response: HttpResponse) -> HttpResponse {
+    let mut access = access_cookie(String::new());
+    access.set_max_age(Duration::days(-1));
+    let mut refresh = refresh_cookie(String::new());
+    refresh.set_max_age(Duration::days(-1));
+    if let Err(e) = response
+        .add_cookie(&access)
+        .and_then(|_| response.add_cookie(&refresh))
+    {
+        error!("Error clearing session cookies {:?}", e);
+    }
+    response
+}
+
+#[actix_web::post("/refresh")]
+pub async fn refresh_session(req: HttpRequest, db: Data<DB>, jwt: Data<JWT>) -> impl Responder {
+    let token = match req.cookie("refresh_token") {
+        Some(cookie) => cookie.value().to_string(),
+        None => {
+            return Response::<String>::error("Missing refresh token!", StatusCode::UNAUTHORIZED);
+        }
+    };
+    let claims = match jwt.decode_refresh(&token) {
+        Ok(claims) => claims,
+        Err(_) => {
+            return clear_session_cookies(Response::<String>::error(
+                "Invalid or expired refresh token!",
+                StatusCode::UNAUTHORIZED,
+            ));
+        }
+    };
+    let session = match db.sessions.find_active(&claims.sid).await {
+        Ok(Some(session)) => session,
+        Ok(None) => {
+            return clear_session_cookies(Response::<String>::error(
+                "Session expired or revoked!",
+                StatusCode::UNAUTHORIZED,
+            ));
+        }
+        Err(e) => {
+            error!("Error fetching session {:?}", e);
+            return Response::<String>::error(
+                "Something went wrong!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+
+    // a refresh token can be redeemed once, seeing an old one again means it
+    // was copied, so the whole session is killed for both parties
+    let next_token_id = nanoid!();
+    let rotated = session.refresh_token_id == claims.jti
+        && match db
+            .sessions
+            .rotate(&session.session_id, &claims.jti, &next_token_id)
+            .await
+        {
+            Ok(rotated) => rotated,
+            Err(e) => {
+                error!("Error rotating refresh token {:?}", e);
+                return Response::<String>::error(
+                    "Something went wrong!",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                );
+            }
+        };
+    if !rotated {
+        warn!(
+            "Refresh token reuse detected for session {} of {}",
+            session.session_id, session.username
+        );
+        if let Err(e) = db
+            .sessions
+            .revoke(&session.session_id, &session.username)
+            .await
+        {
+            error!("Error revoking reused session {:?}", e);
+        }
+        return clear_session_cookies(Response::<String>::error(
+            "Refresh token already used, please log in again!",
+            StatusCode::UNAUTHORIZED,
+        ));
+    }
+
+    let uuid = match db.users.search_by_username(&session.username).await {
+        Ok(Some(user)) => user.uuid,
+        Ok(None) => {
+            return clear_session_cookies(Response::<String>::error(
+                "No user found!",
+                StatusCode::UNAUTHORIZED,
+            ));
+        }
+        Err(e) => {
+            error!("Error fetching user {:?}", e);
+            return Response::<String>::error(
+                "Something went wrong!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+
+    let tokens = jwt
+        .sign(uuid, session.session_id.clone())
+        .and_then(|access_token| {
+            jwt.sign_refresh(session.session_id, next_token_id)
+                .map(|refresh_token| (access_token, refresh_token))
+        });
+    match tokens {
+        Ok((access_token, refresh_token)) => HttpResponse::Ok()
+            .cookie(access_cookie(access_token))
+            .cookie(refresh_cookie(refresh_token))
+            .json("Session refreshed!"),
+        Err(e) => {
+            error!("Error generating the jwt token {}", e);
+            Response::<String>::error("Something went wrong!", StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
 }
 
-pub fn init(cnf: &mut ServiceConfig) -> () {
+#[actix_web::get("/logout")]
+pub async fn logout_user(req: HttpRequest, db: Data<DB>, jwt: Data<JWT>) -> impl Responder {
+    let session_id = req
+        .cookie("refresh_token")
+        .and_then(|cookie| jwt.decode_refresh(cookie.value()).ok())
+        .map(|claims| claims.sid)
+        .or_else(|| {
+            req.cookie("auth_token")
+                .and_then(|cookie| jwt.decode(cookie.value()).ok())
+                .map(|claims| claims.sid)
+        });
+    if let Some(session_id) = session_id {
+        if let Ok(Some(session)) = db.sessions.find_active(&session_id).await {
+            if let Err(e) = db.sessions.revoke(&session_id, &session.username).await {
+                error!("Error revoking session on logout {:?}", e);
+            }
+        }
+    }
+    clear_session_cookies(HttpResponse::Created().json("User logged out!"))
+}
+
+pub fn init(cnf: &mut ServiceConfig) {
     cnf.service(start_registration)
         .service(finish_registration)
         .service(start_authentication)
         .service(finish_authentication)
-        .service(logout_user);
-    ()
+        .service(start_discoverable_authentication)
+        .service(finish_discoverable_authentication)
+        .service(refresh_session)
+        .service(logout_user)
+        .service(
+            scope("/sessions")
+                .wrap(from_fn(authenticate_user))
+                .configure(session_routes::init),
+        )
+        .service(
+            scope("/credentials")
+                .wrap(from_fn(authenticate_user))
+                .configure(credential_routes::init),
+        );
 }
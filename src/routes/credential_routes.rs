@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+
+use actix_web::{
+    http::StatusCode,
+    web::{Data, Json, Path, Query, ServiceConfig},
+    Responder,
+};
+use chrono::Utc;
+use log::error;
+use mongodb::bson::{self, oid::ObjectId};
+use serde::Deserialize;
+use webauthn_rs::{
+    prelude::{CredentialID, Passkey, PasskeyRegistration, RegisterPublicKeyCredential, Uuid},
+    Webauthn,
+};
+
+use crate::{
+    db::{
+        credentials_repo::{encode_cred_id, Credential, Revocation},
+        reg_state_repo::RegState,
+        DB,
+    },
+    middlewares::authenticate::AuthenticatedUser,
+    models::auth_api_model::CredentialResponse,
+    utils::json_responder::Response,
+    webauthn::require_resident_key,
+};
+
+#[derive(Deserialize, Debug)]
+struct NicknameParams {
+    nickname: Option<String>,
+}
+
+#[actix_web::get("")]
+pub async fn list_credentials(db: Data<DB>, user: AuthenticatedUser) -> impl Responder {
+    match db.credentials.find_by_username(&user.username).await {
+        Ok(credentials) => {
+            let credentials: Vec<CredentialResponse> = credentials
+                .into_iter()
+                .map(CredentialResponse::from)
+                .collect();
+            Response::ok(credentials, StatusCode::OK)
+        }
+        Err(e) => {
+            error!("Error listing credentials of {} {:?}", user.username, e);
+            Response::<String>::error(
+                "Failed fetching credentials!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    }
+}
+
+#[actix_web::post("/register/start")]
+pub async fn start_credential_registration(
+    db: Data<DB>,
+    webauthn: Data<Webauthn>,
+    user: AuthenticatedUser,
+) -> impl Responder {
+    let uuid = match Uuid::parse_str(&user.uuid) {
+        Ok(uuid) => uuid,
+        Err(e) => {
+            error!("Stored uuid of {} is invalid {:?}", user.username, e);
+            return Response::<()>::error(
+                "Something went wrong!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+
+    // an authenticator that is already registered can't be added twice
+    let existing_keys: Vec<CredentialID> =
+        match db.credentials.find_by_username(&user.username).await {
+            Ok(credentials) => credentials
+                .iter()
+                .filter_map(|credential| credential.to_passkey().ok())
+                .map(|passkey| passkey.cred_id().clone())
+                .collect(),
+            Err(e) => {
+                error!("Error fetching credentials of {} {:?}", user.username, e);
+                return Response::<()>::error(
+                    "Something went wrong!",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                );
+            }
+        };
+
+    let (mut ccr, reg_state) = match webauthn.start_passkey_registration(
+        uuid,
+        &user.username,
+        &user.username,
+        Some(existing_keys),
+    ) {
+        Ok(data) => data,
+        Err(e) => {
+            error!("Error creating challange and reg state {}", e);
+            return Response::<()>::error(
+                "Something went wrong!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+    require_resident_key(&mut ccr);
+    let reg_state = match serde_json::to_value(&reg_state) {
+        Ok(value) => value,
+        Err(e) => {
+            error!("Error serializing reg_state {}", e);
+            return Response::<()>::error(
+                "Something went wrong!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+
+    let new_reg_state = RegState {
+        username: user.username,
+        uuid: user.uuid,
+        reg_state,
+        created_at: bson::DateTime::now(),
+    };
+
+    match db.reg_states.insert_passkey(new_reg_state).await {
+        Ok(_success) => Response::ok(ccr, StatusCode::OK),
+        Err(e) => {
+            error!("Error storing reg state to db {}", e);
+            Response::<()>::error("Something went wrong", StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[actix_web::post("/register/finish")]
+pub async fn finish_credential_registration(
+    db: Data<DB>,
+    webauthn: Data<Webauthn>,
+    user: AuthenticatedUser,
+    Query(params): Query<NicknameParams>,
+    request: Json<RegisterPublicKeyCredential>,
+) -> impl Responder {
+    let reg_state = match db.reg_states.take_passkey(&user.username).await {
+        Ok(Some(reg_state)) if reg_state.uuid == user.uuid => reg_state.reg_state,
+        Ok(_) => {
+            return Response::<String>::error(
+                "No registration init found or it has expired!",
+                StatusCode::BAD_REQUEST,
+            );
+        }
+        Err(e) => {
+            error!("Error fetching reg state of {} {:?}", user.username, e);
+            return Response::<String>::error(
+                "Something went wrong!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+
+    let reg_state: PasskeyRegistration = match serde_json::from_value(reg_state) {
+        Ok(data) => data,
+        Err(e) => {
+            error!("Failed deserializing registration state {:?}", e);
+            return Response::<String>::error(
+                "Something went wrong!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+
+    let pass_key: Passkey = match webauthn.finish_passkey_registration(&request, &reg_state) {
+        Ok(key) => key,
+        Err(e) => {
+            error!("Failed generating the passkey {:?}", e);
+            return Response::<String>::error("Failed generating passkey", StatusCode::BAD_REQUEST);
+        }
+    };
+
+    let nickname = match params.nickname {
+        Some(nickname) if !nickname.trim().is_empty() => nickname.trim().to_string(),
+        _ => {
+            let count = db
+                .credentials
+                .count_by_username(&user.username)
+                .await
+                .unwrap_or(0);
+            format!("Passkey {}", count + 1)
+        }
+    };
+
+    let cred_id = encode_cred_id(pass_key.cred_id());
+    let passkey = match serde_json::to_value(&pass_key) {
+        Ok(data) => data,
+        Err(e) => {
+            error!("Failed serializing the key {}", e);
+            return Response::<String>::error(
+                "Failed generating passkey!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+
+    let credential = Credential {
+        id: ObjectId::new(),
+        username: user.username,
+        cred_id,
+        nickname,
+        passkey,
+        created_at: Utc::now(),
+        last_used_at: None,
+    };
+
+    match db.credentials.insert(credential).await {
+        Ok(inserted) => Response::ok(inserted, StatusCode::CREATED),
+        Err(e) => {
+            error!("Failed storing credential {:?}", e);
+            Response::<String>::error("Failed adding passkey!", StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[actix_web::patch("/{id}")]
+pub async fn rename_credential(
+    db: Data<DB>,
+    user: AuthenticatedUser,
+    id: Path<String>,
+    Json(req): Json<HashMap<String, String>>,
+) -> impl Responder {
+    let id = match ObjectId::parse_str(id.as_str()) {
+        Ok(id) => id,
+        Err(_) => {
+            return Response::<String>::error("Invalid credential id!", StatusCode::BAD_REQUEST);
+        }
+    };
+    let nickname = match req.get("nickname") {
+        Some(nickname) if !nickname.trim().is_empty() => nickname.trim(),
+        _ => {
+            return Response::<String>::error("Need a nickname!", StatusCode::BAD_REQUEST);
+        }
+    };
+    match db.credentials.rename(id, &user.username, nickname).await {
+        Ok(true) => Response::ok("Passkey renamed!", StatusCode::OK),
+        Ok(false) => Response::<String>::error("No such passkey!", StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Error renaming credential {:?}", e);
+            Response::<String>::error(
+                "Failed renaming passkey!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    }
+}
+
+#[actix_web::delete("/{id}")]
+pub async fn revoke_credential(
+    db: Data<DB>,
+    user: AuthenticatedUser,
+    id: Path<String>,
+) -> impl Responder {
+    let id = match ObjectId::parse_str(id.as_str()) {
+        Ok(id) => id,
+        Err(_) => {
+            return Response::<String>::error("Invalid credential id!", StatusCode::BAD_REQUEST);
+        }
+    };
+    match db.credentials.revoke(id, &user.username, &db).await {
+        Ok(Revocation::Revoked) => Response::ok("Passkey revoked!", StatusCode::OK),
+        Ok(Revocation::NotFound) => {
+            Response::<String>::error("No such passkey!", StatusCode::NOT_FOUND)
+        }
+        Ok(Revocation::LastPasskey) => {
+            Response::<String>::error("Cannot remove your only passkey!", StatusCode::CONFLICT)
+        }
+        Err(e) => {
+            error!("Error revoking credential {:?}", e);
+            Response::<String>::error(
+                "Failed revoking passkey!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    }
+}
+
+pub fn init(cnf: &mut ServiceConfig) {
+    cnf.service(list_credentials)
+        .service(start_credential_registration)
+        .service(finish_credential_registration)
+        .service(rename_credential)
+        .service(revoke_credential);
+}
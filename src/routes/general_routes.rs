@@ -1,10 +1,9 @@
-use std::sync::{Arc, Mutex};
-
 use actix_web::{
     http::StatusCode,
     web::{self, Data, ServiceConfig},
     Responder,
 };
+use log::error;
 use serde::Deserialize;
 
 use crate::{db::DB, utils::json_responder::Response};
@@ -17,10 +16,9 @@ struct PaginationParams {
 
 #[actix_web::get("/live")]
 pub async fn get_live_polls(
-    db: Data<Arc<Mutex<DB>>>,
+    db: Data<DB>,
     web::Query(params): web::Query<PaginationParams>,
 ) -> impl Responder {
-    let db = db.lock().unwrap();
     let page = params.page.unwrap_or(1);
     let per_page = params.per_page.unwrap_or(2);
 
@@ -59,10 +57,9 @@ pub async fn get_live_polls(
 
 #[actix_web::get("/closed")]
 pub async fn get_closed_polls(
-    db: Data<Arc<Mutex<DB>>>,
+    db: Data<DB>,
     web::Query(params): web::Query<PaginationParams>,
 ) -> impl Responder {
-    let db = db.lock().unwrap();
     let page = params.page.unwrap_or(1);
     let per_page = params.per_page.unwrap_or(10);
 
@@ -99,7 +96,47 @@ pub async fn get_closed_polls(
     )
 }
 
+#[actix_web::get("/upcoming")]
+pub async fn get_upcoming_polls(
+    db: Data<DB>,
+    web::Query(params): web::Query<PaginationParams>,
+) -> impl Responder {
+    let page = params.page.unwrap_or(1);
+    let per_page = params.per_page.unwrap_or(10);
+
+    let polls = match db.polls.get_upcoming_polls(page, per_page).await {
+        Ok(polls) => polls,
+        Err(e) => {
+            error!("Error fetching upcoming polls {:?}", e);
+            return Response::<String>::error(
+                "Failed fetching upcoming polls!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+
+    let total_polls = match db.polls.count_upcoming_polls().await {
+        Ok(count) => count,
+        Err(e) => {
+            error!("Error fetching upcoming polls count {:?}", e);
+            0
+        }
+    };
+
+    Response::ok(
+        serde_json::json!({
+            "polls": polls,
+            "page": page,
+            "per_page": per_page,
+            "total_polls": total_polls,
+            "total_pages": (total_polls as f64 / per_page as f64).ceil() as u64
+        }),
+        StatusCode::OK,
+    )
+}
+
 pub fn init(cnf: &mut ServiceConfig) {
-    cnf.service(get_live_polls).service(get_closed_polls);
-    ()
+    cnf.service(get_live_polls)
+        .service(get_closed_polls)
+        .service(get_upcoming_polls);
 }
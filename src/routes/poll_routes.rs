@@ -1,15 +1,14 @@
 use actix_web::{
-    http::StatusCode,
+    http::{header::ContentDisposition, StatusCode},
     web::{self, Data, Json, Path, ServiceConfig},
-    Responder,
+    HttpMessage, HttpRequest, HttpResponse, Responder,
 };
-use chrono::Utc;
-use log::error;
+use futures::TryStreamExt;
+use log::{debug, error};
 use mongodb::bson::oid::ObjectId;
-use nanoid::nanoid;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    str,
     sync::{Arc, Mutex},
 };
 
@@ -22,64 +21,46 @@ struct PaginationParams {
 }
 
 use crate::{
-    db::{options_repo::OptionModel, polls_repo::Poll, DB},
-    models::poll_api_model::{NewPollRequest, PollResults},
-    sse::Broadcaster,
+    db::{
+        polls_repo::{PollEditRequest, Visibility},
+        votes_repo::{Ballot, OptionScore},
+        DB,
+    },
+    errors::PollError,
+    export::{self, ExportData},
+    import::{self, ImportReport, ImportedPoll, RowError},
+    middlewares::authenticate::AuthenticatedUser,
+    models::poll_api_model::{
+        CastVoteRequest, EditPollRequest, ExportParams, ImportParams, NewInviteRequest,
+        NewPollRequest, PollAccessParams,
+    },
+    sse::{self, Broadcaster},
+    tabulation::quadratic::Allocation,
     utils::json_responder::Response,
 };
 
 #[actix_web::post("/new")]
-pub async fn create_poll(req: Json<NewPollRequest>, db: Data<Arc<Mutex<DB>>>) -> impl Responder {
-    let db = db.lock().unwrap();
-    let poll_data = req.into_inner();
+pub async fn create_poll(
+    req: Json<NewPollRequest>,
+    db: Data<DB>,
+    user: AuthenticatedUser,
+) -> impl Responder {
+    let (new_poll, options) = match req.into_inner().into_poll(&user.username) {
+        Ok(built) => built,
+        Err(reason) => return Response::<String>::error(&reason, StatusCode::BAD_REQUEST),
+    };
     let mut session = db.client.start_session().await.unwrap();
     session.start_transaction().await.unwrap();
-    let title = poll_data.title;
-    let mut option_ids: Vec<ObjectId> = Vec::new();
-    let options = if poll_data.options.len() >= 2 {
-        poll_data.options
-    } else {
-        return Response::<String>::error(
-            "Minimum two options are needed!",
-            StatusCode::BAD_REQUEST,
-        );
-    };
-    let mut option_inserted = true;
     for option in options {
-        let new_option = OptionModel {
-            _id: ObjectId::new(),
-            text: option.text,
-            votes_count: 0,
-        };
-        option_inserted = option_inserted
-            && match db.options.insert(new_option).await {
-                Ok(inserted_option) => {
-                    if let Some(inserted_id) = inserted_option.inserted_id.as_object_id() {
-                        option_ids.push(inserted_id); // Push the ObjectId into the vector
-                        true
-                    } else {
-                        session.abort_transaction().await.unwrap();
-                        error!("Insert succeeded, but no ObjectId returned!");
-                        false
-                    }
-                }
-                Err(e) => {
-                    session.abort_transaction().await.unwrap();
-                    error!("Error writing option! {:?}", e);
-                    false
-                }
-            };
+        if let Err(e) = db.options.insert(option).await {
+            session.abort_transaction().await.unwrap();
+            error!("Error writing option! {:?}", e);
+            return Response::<String>::error(
+                "Error creating poll!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
     }
-    let new_poll = Poll {
-        id: nanoid!(),
-        created_at: Utc::now(),
-        updated_at: Utc::now(),
-        title,
-        options: option_ids,
-        owner_id: poll_data.ownername,
-        is_open: true,
-        voters: Vec::new(),
-    };
     let poll_insert_result = match db.polls.insert(new_poll).await {
         Ok(inserted_poll) => inserted_poll,
         Err(e) => {
@@ -95,204 +76,443 @@ pub async fn create_poll(req: Json<NewPollRequest>, db: Data<Arc<Mutex<DB>>>) ->
     Response::ok(poll_insert_result, StatusCode::OK)
 }
 
+/// Creates many polls from a JSON array of `NewPollRequest`s, or from CSV
+/// (`Content-Type: text/csv`) with one `title,option,option...` row per poll.
+#[actix_web::post("/import")]
+pub async fn import_polls(
+    req: HttpRequest,
+    body: web::Bytes,
+    db: Data<DB>,
+    user: AuthenticatedUser,
+    web::Query(params): web::Query<ImportParams>,
+) -> Result<HttpResponse, PollError> {
+    let rows = if req.content_type() == "text/csv" {
+        let body = str::from_utf8(&body)
+            .map_err(|_| PollError::InvalidPoll("CSV must be UTF-8!".to_string()))?;
+        import::parse_csv(body)
+    } else {
+        import::parse_json(&body)
+    }
+    .map_err(PollError::InvalidPoll)?;
+    if rows.is_empty() {
+        return Err(PollError::InvalidPoll("Nothing to import!".to_string()));
+    }
+    if rows.len() > import::MAX_IMPORT_ROWS {
+        return Err(PollError::InvalidPoll(format!(
+            "At most {} polls can be imported at once!",
+            import::MAX_IMPORT_ROWS
+        )));
+    }
+
+    let mut polls = Vec::new();
+    let mut report = ImportReport {
+        dry_run: params.dry_run,
+        created: 0,
+        polls: Vec::new(),
+        errors: Vec::new(),
+    };
+    // rows are numbered from 1, after any CSV header
+    for (row, request) in (1..).zip(rows) {
+        match request.and_then(|request| request.into_poll(&user.username)) {
+            Ok((poll, options)) => {
+                report.polls.push(ImportedPoll {
+                    row,
+                    id: poll.id.clone(),
+                    title: poll.title.clone(),
+                    options: options.len(),
+                });
+                polls.push((poll, options));
+            }
+            Err(error) => report.errors.push(RowError { row, error }),
+        }
+    }
+    if !report.errors.is_empty() {
+        return Ok(Response::error_with_result(
+            report,
+            "Some rows are invalid, nothing was imported!",
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ));
+    }
+    if !params.dry_run {
+        db.polls.insert_all(&polls, &db).await?;
+        report.created = polls.len();
+    }
+    Ok(Response::ok(report, StatusCode::OK))
+}
+
 #[actix_web::post("/{id}")]
 pub async fn get_poll(
     id: Path<String>,
-    db: Data<Arc<Mutex<DB>>>,
-    Json(username): Json<HashMap<String, String>>,
-) -> impl Responder {
-    let db = db.lock().unwrap();
-    let username = match username.get("username") {
-        Some(username) => username,
-        None => {
-            return Response::<String>::error("Need username", StatusCode::BAD_REQUEST);
-        }
-    };
-    let poll_data = match db.polls.get(id.as_str(), &username).await {
-        Ok(poll_response) => poll_response,
-        Err(e) => {
-            error!("Error finding poll {:?}", e);
-            return Response::<String>::error(
-                "Failed fetching poll!",
-                StatusCode::INTERNAL_SERVER_ERROR,
-            );
-        }
+    db: Data<DB>,
+    user: AuthenticatedUser,
+    web::Query(access): web::Query<PollAccessParams>,
+) -> Result<HttpResponse, PollError> {
+    check_access(&id, &user, &access, &db).await?;
+    let poll_data = db.polls.get(id.as_str(), &user.username).await?;
+    Ok(Response::ok(poll_data, StatusCode::OK))
+}
+
+#[actix_web::patch("/{id}")]
+pub async fn edit_poll(
+    id: Path<String>,
+    db: Data<DB>,
+    user: AuthenticatedUser,
+    broadcaster: Data<Arc<Mutex<Broadcaster>>>,
+    Json(req): Json<EditPollRequest>,
+) -> Result<HttpResponse, PollError> {
+    if req
+        .title
+        .as_ref()
+        .is_some_and(|title| title.trim().is_empty())
+    {
+        return Err(PollError::InvalidPoll("Title can't be empty!".to_string()));
+    }
+    let edit = PollEditRequest {
+        title: req.title,
+        description: req
+            .description
+            .map(|description| Some(description).filter(|d| !d.trim().is_empty())),
+        visibility: req.visibility,
+        results_visibility: req.results_visibility,
+        add_options: req
+            .add_options
+            .into_iter()
+            .map(|option| option.text)
+            .collect(),
+        remove_options: parse_option_ids(&req.remove_options)?,
+        discard_votes: req.discard_votes,
     };
-    Response::ok(poll_data, StatusCode::OK)
+
+    db.polls.edit_poll(&id, &user.username, edit, &db).await?;
+    broadcast_results(&id, &db, &broadcaster).await;
+    Ok(Response::ok("Poll updated!", StatusCode::OK))
 }
 
 #[actix_web::post("/{id}/close")]
 pub async fn close_poll(
     id: Path<String>,
-    db: Data<Arc<Mutex<DB>>>,
-    Json(req): Json<HashMap<String, String>>,
-) -> impl Responder {
-    let db = db.lock().unwrap();
-    let username = match req.get("username") {
-        Some(username) => username.clone(),
-        None => {
-            return Response::<String>::error("Owner username required", StatusCode::BAD_REQUEST);
-        }
-    };
-    let _close_poll = match db.polls.close_poll(id.as_str(), &username).await {
-        Ok(_closed) => {
-            return Response::ok("Poll closed!", StatusCode::OK);
-        }
-        Err(e) => {
-            error!("Error deleting poll {:?}", e);
-            return Response::<String>::error(
-                "Failed closing poll, try again later!",
-                StatusCode::INTERNAL_SERVER_ERROR,
-            );
-        }
-    };
+    db: Data<DB>,
+    user: AuthenticatedUser,
+    broadcaster: Data<Arc<Mutex<Broadcaster>>>,
+) -> Result<HttpResponse, PollError> {
+    db.polls
+        .close_poll(id.as_str(), &user.username, &db)
+        .await?;
+    sse::broadcast_closed(&id, &db, &broadcaster).await;
+    Ok(Response::ok("Poll closed!", StatusCode::OK))
 }
 
 #[actix_web::post("/{id}/delete")]
 pub async fn delete_poll(
     id: Path<String>,
-    db: Data<Arc<Mutex<DB>>>,
-    Json(req): Json<HashMap<String, String>>,
+    db: Data<DB>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, PollError> {
+    db.polls.delete(id.as_str(), &user.username).await?;
+    Ok(Response::ok("Poll moved to trash!", StatusCode::OK))
+}
+
+#[actix_web::get("/trash")]
+pub async fn get_trashed_polls(
+    db: Data<DB>,
+    web::Query(params): web::Query<PaginationParams>,
+    user: AuthenticatedUser,
 ) -> impl Responder {
-    let db = db.lock().unwrap();
-    let username = match req.get("username") {
-        Some(username) => username.clone(),
-        None => {
-            return Response::<String>::error("Owner username required", StatusCode::BAD_REQUEST);
-        }
-    };
-    let _is_poll_deleted = match db.polls.delete(id.as_str(), &username).await {
-        Ok(_) => {
-            return Response::ok("Poll deleted!", StatusCode::OK);
-        }
+    let page = params.page.unwrap_or(1);
+    let per_page = params.per_page.unwrap_or(10);
+
+    let polls = match db
+        .polls
+        .get_trashed_polls(&user.username, page, per_page)
+        .await
+    {
+        Ok(polls) => polls,
         Err(e) => {
-            error!("Error deleting the poll! {:?}", e);
+            error!("Error fetching trashed polls: {}", e);
             return Response::<String>::error(
-                "Failed deleting poll!",
+                "Failed fetching trashed polls!",
                 StatusCode::INTERNAL_SERVER_ERROR,
             );
         }
     };
+
+    let total_polls = match db.polls.count_trashed_polls(&user.username).await {
+        Ok(count) => count,
+        Err(e) => {
+            error!("Error counting trashed polls: {}", e);
+            0
+        }
+    };
+
+    Response::ok(
+        serde_json::json!({
+            "polls": polls,
+            "page": page,
+            "per_page": per_page,
+            "total_polls": total_polls,
+            "total_pages": (total_polls as f64 / per_page as f64).ceil() as u64
+        }),
+        StatusCode::OK,
+    )
+}
+
+#[actix_web::post("/{id}/restore")]
+pub async fn restore_poll(
+    id: Path<String>,
+    db: Data<DB>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, PollError> {
+    db.polls.restore(id.as_str(), &user.username).await?;
+    Ok(Response::ok("Poll restored!", StatusCode::OK))
+}
+
+#[actix_web::post("/{id}/purge")]
+pub async fn purge_poll(
+    id: Path<String>,
+    db: Data<DB>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, PollError> {
+    db.polls.purge(id.as_str(), &user.username, &db).await?;
+    Ok(Response::ok("Poll deleted permanently!", StatusCode::OK))
 }
 
 #[actix_web::post("/{id}/reset")]
 pub async fn reset_poll(
     id: Path<String>,
-    db: Data<Arc<Mutex<DB>>>,
-    Json(req): Json<HashMap<String, String>>,
+    db: Data<DB>,
+    user: AuthenticatedUser,
     broadcaster: Data<Arc<Mutex<Broadcaster>>>,
-) -> impl Responder {
-    let db = db.lock().unwrap();
-    let username = match req.get("username") {
-        Some(username) => username.clone(),
-        None => {
-            return Response::<String>::error("Owner username required!", StatusCode::BAD_REQUEST);
-        }
-    };
-
-    match db.polls.reset_poll(id.as_str(), &db, &username).await {
-        Ok(_) => {
-            let poll_result_data = match db.polls.get_poll_results(&id).await.unwrap() {
-                Some(poll_result) => poll_result,
-                None => PollResults {
-                    id: "1234".to_string(),
-                    title: "Never reaches".to_string(),
-                    options: Vec::new(),
-                    total_votes: 0,
-                },
-            };
-            broadcaster
-                .lock()
-                .unwrap()
-                .send_poll_results(&poll_result_data);
-            return Response::ok("Poll reset successfully!", StatusCode::OK);
-        }
-        Err(e) => {
-            error!("Error resetting poll! {:?}", e);
-            return Response::<String>::error(
-                "Failed resetting poll!",
-                StatusCode::INTERNAL_SERVER_ERROR,
-            );
-        }
-    }
+) -> Result<HttpResponse, PollError> {
+    db.polls
+        .reset_poll(id.as_str(), &db, &user.username)
+        .await?;
+    broadcast_results(&id, &db, &broadcaster).await;
+    Ok(Response::ok("Poll reset successfully!", StatusCode::OK))
 }
 
 #[actix_web::post("/{id}/vote")]
 pub async fn cast_vote(
-    db: Data<Arc<Mutex<DB>>>,
+    db: Data<DB>,
     id: Path<String>,
     broadcaster: Data<Arc<Mutex<Broadcaster>>>,
-    Json(req): Json<HashMap<String, String>>,
-) -> impl Responder {
-    let db = db.lock().unwrap();
-    // 1. Extract and validate username
-    let username = match req.get("username") {
-        Some(username) => username.to_string(),
-        None => {
-            return Response::<String>::error("Need username!", StatusCode::BAD_REQUEST);
-        }
-    };
+    user: AuthenticatedUser,
+    web::Query(access): web::Query<PollAccessParams>,
+    Json(req): Json<CastVoteRequest>,
+) -> Result<HttpResponse, PollError> {
+    // 1. Extract and validate option IDs
+    let ballot = ballot_from_request(req)?;
+    check_access(&id, &user, &access, &db).await?;
 
-    // 2. Extract and validate option ID
-    let option_id = match req.get("optionId") {
-        Some(option_id) => {
-            // Convert option_id string to ObjectId
-            match ObjectId::parse_str(option_id) {
-                Ok(id) => id,
-                Err(e) => {
-                    error!("Error fetchin option in cast vote {}", e);
-                    return Response::<String>::error(
-                        "Invalid option id!",
-                        StatusCode::BAD_REQUEST,
-                    );
-                }
-            }
-        }
-        None => {
-            return Response::<String>::error("Require vote option!", StatusCode::BAD_REQUEST);
-        }
-    };
+    // 2. Attempt to cast vote
+    let receipt = db.polls.add_vote(&id, user.username, ballot, &db).await?;
+    broadcast_results(&id, &db, &broadcaster).await;
+    Ok(Response::ok(receipt, StatusCode::OK))
+}
+
+#[actix_web::post("/{id}/vote/change")]
+pub async fn change_vote(
+    db: Data<DB>,
+    id: Path<String>,
+    broadcaster: Data<Arc<Mutex<Broadcaster>>>,
+    user: AuthenticatedUser,
+    web::Query(access): web::Query<PollAccessParams>,
+    Json(req): Json<CastVoteRequest>,
+) -> Result<HttpResponse, PollError> {
+    let ballot = ballot_from_request(req)?;
+    check_access(&id, &user, &access, &db).await?;
+    let receipt = db
+        .polls
+        .change_vote(&id, &user.username, ballot, &db)
+        .await?;
+    broadcast_results(&id, &db, &broadcaster).await;
+    Ok(Response::ok(receipt, StatusCode::OK))
+}
+
+#[actix_web::post("/{id}/vote/retract")]
+pub async fn retract_vote(
+    db: Data<DB>,
+    id: Path<String>,
+    broadcaster: Data<Arc<Mutex<Broadcaster>>>,
+    user: AuthenticatedUser,
+    web::Query(access): web::Query<PollAccessParams>,
+) -> Result<HttpResponse, PollError> {
+    check_access(&id, &user, &access, &db).await?;
+    let receipt = db.polls.retract_vote(&id, &user.username, &db).await?;
+    broadcast_results(&id, &db, &broadcaster).await;
+    Ok(Response::ok(receipt, StatusCode::OK))
+}
 
-    // 4. Attempt to cast vote
-    match db
+#[actix_web::post("/{id}/access-code")]
+pub async fn rotate_access_code(
+    id: Path<String>,
+    db: Data<DB>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, PollError> {
+    let access_code = db.polls.rotate_access_code(&id, &user.username).await?;
+    Ok(Response::ok(
+        serde_json::json!({ "access_code": access_code }),
+        StatusCode::OK,
+    ))
+}
+
+#[actix_web::post("/{id}/access-code/remove")]
+pub async fn remove_access_code(
+    id: Path<String>,
+    db: Data<DB>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, PollError> {
+    db.polls.remove_access_code(&id, &user.username).await?;
+    Ok(Response::ok("Access code removed!", StatusCode::OK))
+}
+
+#[actix_web::post("/{id}/invites")]
+pub async fn create_invite(
+    id: Path<String>,
+    db: Data<DB>,
+    user: AuthenticatedUser,
+    Json(req): Json<NewInviteRequest>,
+) -> Result<HttpResponse, PollError> {
+    let invite = db
         .polls
-        .add_vote(&id, username.clone(), option_id, &db)
+        .create_invite(&id, &user.username, req.invitee, &db)
+        .await?;
+    Ok(Response::ok(invite, StatusCode::CREATED))
+}
+
+#[actix_web::get("/{id}/invites")]
+pub async fn list_invites(
+    id: Path<String>,
+    db: Data<DB>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, PollError> {
+    let invites = db.polls.list_invites(&id, &user.username, &db).await?;
+    Ok(Response::ok(invites, StatusCode::OK))
+}
+
+#[actix_web::post("/{id}/invites/{token}/revoke")]
+pub async fn revoke_invite(
+    path: Path<(String, String)>,
+    db: Data<DB>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, PollError> {
+    let (id, token) = path.into_inner();
+    db.polls
+        .revoke_invite(&id, &user.username, &token, &db)
+        .await?;
+    Ok(Response::ok("Invite revoked!", StatusCode::OK))
+}
+
+async fn check_access(
+    poll_id: &str,
+    user: &AuthenticatedUser,
+    access: &PollAccessParams,
+    db: &DB,
+) -> Result<(), PollError> {
+    db.polls
+        .check_access(
+            poll_id,
+            &user.username,
+            access.invite.as_deref(),
+            access.code.as_deref(),
+            db,
+        )
         .await
-    {
-        Ok(true) => {
-            let poll_result_data = match db.polls.get_poll_results(&id).await.unwrap() {
-                Some(poll_result) => poll_result,
-                None => PollResults {
-                    id: "1234".to_string(),
-                    title: "Never reaches".to_string(),
-                    options: Vec::new(),
-                    total_votes: 0,
-                },
-            };
-            broadcaster
-                .lock()
-                .unwrap()
-                .send_poll_results(&poll_result_data);
-            return Response::ok("Vote recorded succesfully!", StatusCode::OK);
+}
+
+async fn broadcast_results(poll_id: &str, db: &DB, broadcaster: &Mutex<Broadcaster>) {
+    let poll = match db.polls.find(poll_id).await {
+        Ok(Some(poll)) => poll,
+        Ok(None) => {
+            debug!("No results to broadcast for {}", poll_id);
+            return;
         }
-        Ok(false) => Response::<String>::error(
-            "Unable to cast vote. Poll might be closed or you've already voted.",
-            StatusCode::INTERNAL_SERVER_ERROR,
-        ),
         Err(e) => {
-            error!("Vote casting error: {}", e);
-            Response::<String>::error("Something went wrong!", StatusCode::INTERNAL_SERVER_ERROR)
+            error!("Error checking who sees results of {} {:?}", poll_id, e);
+            return;
         }
+    };
+    // an update per vote would let the owner refetch the counts after each
+    // secret ballot and tie it to whoever just voted
+    if poll.secret_ballot && !poll.is_closed() {
+        return;
+    }
+    // SSE clients are anonymous, so the ids of unlisted and invite-only polls
+    // mustn't reach them. Public polls hiding their results only tell them to
+    // refetch, which goes through `check_access` and the results policy
+    if poll.visibility != Visibility::Public {
+        return;
+    }
+    if !poll.results_public() {
+        broadcaster.lock().unwrap().send_poll_updated(poll_id);
+        return;
+    }
+    match db.polls.get_poll_results(poll_id, db).await {
+        Ok(Some(poll_results)) => broadcaster.lock().unwrap().send_poll_results(&poll_results),
+        Ok(None) => debug!("No results to broadcast for {}", poll_id),
+        Err(e) => error!("Error fetching results to broadcast {:?}", e),
     }
 }
 
+fn ballot_from_request(req: CastVoteRequest) -> Result<Ballot, PollError> {
+    if let Some(allocations) = req.allocations {
+        let allocations = allocations
+            .into_iter()
+            .map(|(option_id, votes)| {
+                let option_id = parse_option_ids(&[option_id])?[0];
+                Ok(Allocation { option_id, votes })
+            })
+            .collect::<Result<Vec<_>, PollError>>()?;
+        return Ok(Ballot::Quadratic { allocations });
+    }
+    if let Some(scores) = req.scores {
+        let scores = scores
+            .into_iter()
+            .map(|(option_id, score)| {
+                let option_id = parse_option_ids(&[option_id])?[0];
+                Ok(OptionScore { option_id, score })
+            })
+            .collect::<Result<Vec<_>, PollError>>()?;
+        return Ok(Ballot::Rating { scores });
+    }
+    if let Some(rankings) = req.rankings {
+        return Ok(Ballot::Ranked {
+            rankings: parse_option_ids(&rankings)?,
+        });
+    }
+    if let Some(option_ids) = req.option_ids {
+        return Ok(Ballot::Approval {
+            option_ids: parse_option_ids(&option_ids)?,
+        });
+    }
+    match req.option_id {
+        Some(option_id) => Ok(Ballot::Single {
+            option_id: parse_option_ids(&[option_id])?[0],
+        }),
+        None => Err(PollError::InvalidBallot("Require vote option!".to_string())),
+    }
+}
+
+fn parse_option_ids(option_ids: &[String]) -> Result<Vec<ObjectId>, PollError> {
+    option_ids
+        .iter()
+        .map(|option_id| {
+            ObjectId::parse_str(option_id).map_err(|e| {
+                debug!("Error parsing option id {}", e);
+                PollError::InvalidOption(format!("Invalid option id {}!", option_id))
+            })
+        })
+        .collect()
+}
+
 #[actix_web::get("/user/{username}")]
 pub async fn get_user_polls(
-    db: Data<Arc<Mutex<DB>>>,
+    db: Data<DB>,
     web::Query(params): web::Query<PaginationParams>,
     username: Path<String>,
+    user: AuthenticatedUser,
 ) -> impl Responder {
-    let db = db.lock().unwrap();
+    // others only see the public polls
+    let include_unlisted = user.username == *username;
     println!("params - {:?}", params);
     let page = params.page.unwrap_or(1);
     let per_page = params.per_page.unwrap_or(4);
@@ -306,6 +526,7 @@ pub async fn get_user_polls(
             per_page,
             sort_by.as_str(),
             sort_order,
+            include_unlisted,
         )
         .await
     {
@@ -321,7 +542,7 @@ pub async fn get_user_polls(
 
     let total_polls = db
         .polls
-        .count_polls_by_username(&username.to_string())
+        .count_polls_by_username(&username.to_string(), include_unlisted)
         .await
         .unwrap();
 
@@ -340,31 +561,106 @@ pub async fn get_user_polls(
 }
 
 #[actix_web::get("/{id}/results")]
-pub async fn get_poll_result(db: Data<Arc<Mutex<DB>>>, id: Path<String>) -> impl Responder {
-    let db = db.lock().unwrap();
+pub async fn get_poll_result(
+    db: Data<DB>,
+    id: Path<String>,
+    user: AuthenticatedUser,
+    web::Query(access): web::Query<PollAccessParams>,
+) -> Result<HttpResponse, PollError> {
     let poll_id = id.as_str();
-    match db.polls.get_poll_results(poll_id).await {
-        Ok(poll_result) => {
-            return Response::ok(poll_result, StatusCode::OK);
+    check_access(poll_id, &user, &access, &db).await?;
+    let poll_result = db
+        .polls
+        .get_visible_results(poll_id, &user.username, &db)
+        .await?;
+    Ok(Response::ok(poll_result, StatusCode::OK))
+}
+
+/// Results or anonymised ballots of the caller's poll as a CSV or NDJSON download.
+#[actix_web::get("/{id}/export")]
+pub async fn export_poll(
+    db: Data<DB>,
+    id: Path<String>,
+    user: AuthenticatedUser,
+    web::Query(params): web::Query<ExportParams>,
+) -> Result<HttpResponse, PollError> {
+    let poll = db.polls.find_exportable(&id, &user.username).await?;
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(params.format.content_type())
+        .insert_header(ContentDisposition::attachment(format!(
+            "{}-{}.{}",
+            poll.id,
+            params.data.name(),
+            params.format.extension()
+        )));
+    match params.data {
+        ExportData::Results => {
+            let results = db
+                .polls
+                .get_poll_results(&poll.id, &db)
+                .await?
+                .ok_or(PollError::NotFound)?;
+            Ok(response.body(export::results(&results, params.format)?))
         }
-        Err(e) => {
-            error!("Error fetching poll results! {:?}", e);
-            return Response::<String>::error(
-                "Error fetching poll results!",
-                StatusCode::INTERNAL_SERVER_ERROR,
-            );
+        ExportData::Ballots => {
+            let ballots = export::ballots(&poll, params.format, &db).await?;
+            // headers are already out, a failure can only cut the download short
+            Ok(response.streaming(ballots.map_err(|e| {
+                error!("Error exporting ballots {:?}", e);
+                actix_web::error::ErrorInternalServerError("Export failed!")
+            })))
         }
-    };
+    }
+}
+
+#[actix_web::get("/{id}/log")]
+pub async fn get_vote_log(
+    db: Data<DB>,
+    id: Path<String>,
+    user: AuthenticatedUser,
+    web::Query(access): web::Query<PollAccessParams>,
+) -> Result<HttpResponse, PollError> {
+    check_access(&id, &user, &access, &db).await?;
+    let vote_log = db.polls.vote_log(&id, &db).await?;
+    Ok(Response::ok(vote_log, StatusCode::OK))
+}
+
+#[actix_web::get("/{id}/log/{hash}")]
+pub async fn get_receipt_proof(
+    db: Data<DB>,
+    path: Path<(String, String)>,
+    user: AuthenticatedUser,
+    web::Query(access): web::Query<PollAccessParams>,
+) -> Result<HttpResponse, PollError> {
+    let (id, hash) = path.into_inner();
+    check_access(&id, &user, &access, &db).await?;
+    let proof = db.polls.receipt_proof(&id, &hash, &db).await?;
+    Ok(Response::ok(proof, StatusCode::OK))
 }
 
 pub fn init(cnf: &mut ServiceConfig) {
     cnf.service(create_poll)
+        .service(import_polls)
         .service(get_poll)
+        .service(edit_poll)
         .service(cast_vote)
+        .service(change_vote)
+        .service(retract_vote)
         .service(close_poll)
         .service(get_user_polls)
         .service(reset_poll)
         .service(delete_poll)
-        .service(get_poll_result);
-    ()
+        .service(get_trashed_polls)
+        .service(restore_poll)
+        .service(purge_poll)
+        .service(get_poll_result)
+        .service(export_poll)
+        .service(get_vote_log)
+        .service(get_receipt_proof)
+        .service(rotate_access_code)
+        .service(remove_access_code)
+        .service(create_invite)
+        .service(list_invites)
+        .service(revoke_invite);
 }
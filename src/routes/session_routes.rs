@@ -0,0 +1,74 @@
+use actix_web::{
+    http::StatusCode,
+    web::{Data, Path, ServiceConfig},
+    HttpResponse, Responder,
+};
+use log::error;
+
+use crate::{
+    db::DB, middlewares::authenticate::AuthenticatedUser, models::auth_api_model::SessionResponse,
+    routes::auth_routes::clear_session_cookies, utils::json_responder::Response,
+};
+
+#[actix_web::get("")]
+pub async fn list_sessions(db: Data<DB>, user: AuthenticatedUser) -> impl Responder {
+    match db.sessions.find_by_username(&user.username).await {
+        Ok(sessions) => {
+            let sessions: Vec<SessionResponse> = sessions
+                .into_iter()
+                .map(|session| SessionResponse::from_session(session, &user.session_id))
+                .collect();
+            Response::ok(sessions, StatusCode::OK)
+        }
+        Err(e) => {
+            error!("Error listing sessions of {} {:?}", user.username, e);
+            Response::<String>::error(
+                "Failed fetching sessions!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    }
+}
+
+#[actix_web::delete("/{id}")]
+pub async fn revoke_session(
+    db: Data<DB>,
+    user: AuthenticatedUser,
+    id: Path<String>,
+) -> impl Responder {
+    match db.sessions.revoke(id.as_str(), &user.username).await {
+        Ok(true) if id.as_str() == user.session_id => {
+            clear_session_cookies(HttpResponse::Ok().json("Logged out!"))
+        }
+        Ok(true) => Response::ok("Session revoked!", StatusCode::OK),
+        Ok(false) => Response::<String>::error("No such session!", StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Error revoking session {:?}", e);
+            Response::<String>::error(
+                "Failed revoking session!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    }
+}
+
+// "log out all devices", including the one making the request
+#[actix_web::post("/revoke-all")]
+pub async fn revoke_all_sessions(db: Data<DB>, user: AuthenticatedUser) -> impl Responder {
+    match db.sessions.revoke_all(&user.username).await {
+        Ok(_revoked) => clear_session_cookies(HttpResponse::Ok().json("Logged out everywhere!")),
+        Err(e) => {
+            error!("Error revoking sessions of {} {:?}", user.username, e);
+            Response::<String>::error(
+                "Failed revoking sessions!",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+    }
+}
+
+pub fn init(cnf: &mut ServiceConfig) {
+    cnf.service(list_sessions)
+        .service(revoke_session)
+        .service(revoke_all_sessions);
+}
@@ -0,0 +1,69 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use actix_web::web::Data;
+use chrono::Utc;
+use log::{error, info};
+use tokio::time::interval;
+
+use crate::{
+    db::DB,
+    sse::{self, Broadcaster},
+};
+
+/// Opens and closes polls at their `opens_at`/`closes_at` deadlines and
+/// purges polls that sat in the trash longer than `trash_retention`. Each
+/// poll is flipped with a single conditional update, so running this on
+/// several instances never opens or closes a poll twice.
+pub async fn run_poll_schedule(
+    db: Data<DB>,
+    broadcaster: Data<Arc<Mutex<Broadcaster>>>,
+    every: Duration,
+    trash_retention: Duration,
+) {
+    let mut ticker = interval(every);
+    loop {
+        ticker.tick().await;
+
+        loop {
+            match db.polls.open_next_due().await {
+                Ok(Some(poll)) => info!("Opened scheduled poll {}", poll.id),
+                Ok(None) => break,
+                Err(e) => {
+                    error!("Error opening scheduled polls {:?}", e);
+                    break;
+                }
+            }
+        }
+
+        loop {
+            let poll = match db.polls.close_next_due().await {
+                Ok(Some(poll)) => poll,
+                Ok(None) => break,
+                Err(e) => {
+                    error!("Error closing scheduled polls {:?}", e);
+                    break;
+                }
+            };
+            info!("Closed scheduled poll {}", poll.id);
+            if let Err(e) = db.polls.seal(&poll.id, &db).await {
+                error!("Error sealing closed poll {:?}", e);
+            }
+            sse::broadcast_closed(&poll.id, &db, &broadcaster).await;
+        }
+
+        let deleted_before = Utc::now() - trash_retention;
+        loop {
+            match db.polls.purge_next_expired(deleted_before, &db).await {
+                Ok(Some(poll)) => info!("Purged trashed poll {}", poll.id),
+                Ok(None) => break,
+                Err(e) => {
+                    error!("Error purging trashed polls {:?}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
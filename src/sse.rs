@@ -2,22 +2,33 @@ use actix_web::web::{Bytes, Data};
 use actix_web::Error;
 
 use futures::stream::Stream;
+use serde::Serialize;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::time::{interval, Duration};
 
 use std::pin::Pin;
 use std::sync::{Arc, Mutex};
 
-use crate::models::poll_api_model::PollResults;
+use log::error;
+
+use crate::{
+    db::{polls_repo::Visibility, DB},
+    models::poll_api_model::PollResults,
+};
 
 pub struct Broadcaster {
     clients: Vec<Sender<Bytes>>,
 }
 
+impl Default for Broadcaster {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Broadcaster {
     pub fn create() -> Data<Arc<Mutex<Self>>> {
-        let me = Data::new(Arc::new(Mutex::new(Broadcaster::new())));
-        me
+        Data::new(Arc::new(Mutex::new(Broadcaster::new())))
     }
 
     pub fn new() -> Self {
@@ -65,12 +76,23 @@ impl Broadcaster {
     }
 
     pub fn send_poll_results(&self, response: &PollResults) {
+        self.send_event("poll_results", response);
+    }
+
+    /// Final results of a poll that just closed.
+    pub fn send_poll_closed(&self, response: &PollResults) {
+        self.send_event("poll_closed", response);
+    }
+
+    /// Counts of the poll changed but its results aren't public, clients refetch them.
+    pub fn send_poll_updated(&self, poll_id: &str) {
+        self.send_event("poll_updated", &serde_json::json!({ "poll_id": poll_id }));
+    }
+
+    fn send_event<T: Serialize>(&self, event: &str, response: &T) {
         let poll_result_json = format!("{:?}", serde_json::to_string(response).unwrap());
 
-        let msg = Bytes::from(format!(
-            "event: poll_results\ndata: {}\n\n",
-            poll_result_json
-        ));
+        let msg = Bytes::from(format!("event: {}\ndata: {}\n\n", event, poll_result_json));
 
         for client in &self.clients {
             let _ = client.clone().try_send(msg.clone());
@@ -78,6 +100,31 @@ impl Broadcaster {
     }
 }
 
+/// Tells clients a poll closed, by hand or on schedule. Only public results
+/// go out, see `Poll::results_public`, other public polls get a `poll_updated`
+/// and the rest nothing, as their ids aren't for anonymous clients.
+pub async fn broadcast_closed(poll_id: &str, db: &DB, broadcaster: &Mutex<Broadcaster>) {
+    match db.polls.find(poll_id).await {
+        Ok(Some(poll)) if poll.results_public() => (),
+        Ok(Some(poll)) => {
+            if poll.visibility == Visibility::Public {
+                broadcaster.lock().unwrap().send_poll_updated(poll_id);
+            }
+            return;
+        }
+        Ok(None) => return,
+        Err(e) => {
+            error!("Error checking who sees results of {} {:?}", poll_id, e);
+            return;
+        }
+    }
+    match db.polls.get_poll_results(poll_id, db).await {
+        Ok(Some(results)) => broadcaster.lock().unwrap().send_poll_closed(&results),
+        Ok(None) => (),
+        Err(e) => error!("Error fetching results of closed poll {:?}", e),
+    }
+}
+
 // Wrap Receiver in own type with correct error handling
 pub struct Client(Receiver<Bytes>);
 
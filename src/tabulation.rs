@@ -0,0 +1,4 @@
+pub mod instant_runoff;
+pub mod quadratic;
+pub mod rating;
+pub mod schulze;
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tally {
+    #[serde(rename = "_id")]
+    pub option_id: ObjectId,
+    pub votes: u64,
+}
+
+/// Ballots moved off an eliminated option. `to` is None for ballots that
+/// ranked no remaining option and became exhausted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Transfer {
+    pub from: ObjectId,
+    pub to: Option<ObjectId>,
+    pub votes: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RunoffRound {
+    pub round: u32,
+    // continuing options only, in poll order
+    pub tallies: Vec<Tally>,
+    // ballots with no continuing option left, counted up to this round
+    pub exhausted: u64,
+    pub eliminated: Option<ObjectId>,
+    pub transfers: Vec<Transfer>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Runoff {
+    pub rounds: Vec<RunoffRound>,
+    pub winner: Option<ObjectId>,
+}
+
+fn top_choice(ballot: &[ObjectId], continuing: &[ObjectId]) -> Option<ObjectId> {
+    ballot.iter().find(|id| continuing.contains(id)).copied()
+}
+
+/// Tie-break for the last place: among the options tied for fewest votes,
+/// drop the one with fewer votes in the most recent earlier round where they
+/// differ. If they tied in every round, drop the one listed last on the poll.
+fn pick_elimination(continuing: &[ObjectId], history: &[HashMap<ObjectId, u64>]) -> ObjectId {
+    let mut tied = continuing.to_vec();
+    for counts in history.iter().rev() {
+        let fewest = tied.iter().map(|id| counts[id]).min().unwrap_or(0);
+        tied.retain(|id| counts[id] == fewest);
+        if tied.len() == 1 {
+            break;
+        }
+    }
+    *tied
+        .last()
+        .expect("an election always has a continuing option")
+}
+
+/// Counts ranked `ballots` by instant runoff. Each round every ballot counts
+/// for its highest ranked continuing option; an option with more than half of
+/// the non-exhausted ballots wins, otherwise the last placed option (see
+/// [`pick_elimination`]) is eliminated and its ballots move on to their next
+/// preference. `options` is the poll's option order, which keeps the output
+/// and tie-breaks deterministic.
+pub fn tabulate(options: &[ObjectId], ballots: &[Vec<ObjectId>]) -> Runoff {
+    let mut continuing = options.to_vec();
+    let mut history: Vec<HashMap<ObjectId, u64>> = Vec::new();
+    let mut rounds = Vec::new();
+
+    loop {
+        let mut counts: HashMap<ObjectId, u64> = continuing.iter().map(|id| (*id, 0)).collect();
+        let mut exhausted = 0;
+        for ballot in ballots {
+            match top_choice(ballot, &continuing) {
+                Some(id) => *counts.entry(id).or_default() += 1,
+                None => exhausted += 1,
+            }
+        }
+        let live_ballots = ballots.len() as u64 - exhausted;
+        let tallies = continuing
+            .iter()
+            .map(|id| Tally {
+                option_id: *id,
+                votes: counts[id],
+            })
+            .collect();
+        let round = rounds.len() as u32 + 1;
+
+        let majority = continuing
+            .iter()
+            .find(|id| counts[*id] * 2 > live_ballots)
+            .copied();
+        if majority.is_some() || live_ballots == 0 {
+            rounds.push(RunoffRound {
+                round,
+                tallies,
+                exhausted,
+                eliminated: None,
+                transfers: Vec::new(),
+            });
+            return Runoff {
+                rounds,
+                winner: majority,
+            };
+        }
+
+        history.push(counts);
+        let eliminated = pick_elimination(&continuing, &history);
+        let remaining: Vec<ObjectId> = continuing
+            .iter()
+            .filter(|id| **id != eliminated)
+            .copied()
+            .collect();
+
+        let mut moved: HashMap<Option<ObjectId>, u64> = HashMap::new();
+        for ballot in ballots {
+            if top_choice(ballot, &continuing) == Some(eliminated) {
+                *moved.entry(top_choice(ballot, &remaining)).or_default() += 1;
+            }
+        }
+        let transfers = remaining
+            .iter()
+            .map(|id| Some(*id))
+            .chain(std::iter::once(None))
+            .filter_map(|to| {
+                moved.get(&to).map(|votes| Transfer {
+                    from: eliminated,
+                    to,
+                    votes: *votes,
+                })
+            })
+            .collect();
+
+        rounds.push(RunoffRound {
+            round,
+            tallies,
+            exhausted,
+            eliminated: Some(eliminated),
+            transfers,
+        });
+        continuing = remaining;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ballots(groups: &[(usize, &[ObjectId])]) -> Vec<Vec<ObjectId>> {
+        groups
+            .iter()
+            .flat_map(|(count, ranking)| std::iter::repeat_n(ranking.to_vec(), *count))
+            .collect()
+    }
+
+    fn options<const N: usize>() -> [ObjectId; N] {
+        std::array::from_fn(|_| ObjectId::new())
+    }
+
+    #[test]
+    fn first_round_majority_wins_outright() {
+        let [a, b] = options();
+        let runoff = tabulate(&[a, b], &ballots(&[(3, &[a, b]), (2, &[b, a])]));
+        assert_eq!(runoff.winner, Some(a));
+        assert_eq!(runoff.rounds.len(), 1);
+        assert_eq!(runoff.rounds[0].eliminated, None);
+    }
+
+    #[test]
+    fn eliminated_ballots_transfer_to_next_preference() {
+        let [a, b, c] = options();
+        let runoff = tabulate(
+            &[a, b, c],
+            &ballots(&[(4, &[a, b, c]), (3, &[b, c, a]), (2, &[c, b, a])]),
+        );
+        assert_eq!(runoff.rounds[0].eliminated, Some(c));
+        assert_eq!(
+            runoff.rounds[0].transfers,
+            vec![Transfer {
+                from: c,
+                to: Some(b),
+                votes: 2
+            }]
+        );
+        assert_eq!(runoff.rounds[1].tallies[1].votes, 5);
+        assert_eq!(runoff.winner, Some(b));
+    }
+
+    #[test]
+    fn ties_fall_back_to_earlier_rounds() {
+        let [a, b, c, d] = options();
+        let runoff = tabulate(
+            &[a, b, c, d],
+            &ballots(&[(4, &[a]), (3, &[b]), (2, &[c]), (1, &[d, c])]),
+        );
+        // b and c tie on 3 in round two, c had fewer in round one
+        let eliminated: Vec<_> = runoff.rounds.iter().map(|r| r.eliminated).collect();
+        assert_eq!(eliminated, vec![Some(d), Some(c), None]);
+        assert_eq!(runoff.rounds[2].exhausted, 3);
+        assert_eq!(runoff.winner, Some(a));
+    }
+
+    #[test]
+    fn full_ties_eliminate_the_last_listed_option() {
+        let [a, b, c] = options();
+        let runoff = tabulate(&[a, b, c], &ballots(&[(2, &[a]), (2, &[b]), (1, &[c])]));
+        assert_eq!(runoff.rounds[1].eliminated, Some(b));
+        assert_eq!(
+            runoff.rounds[1].transfers,
+            vec![Transfer {
+                from: b,
+                to: None,
+                votes: 2
+            }]
+        );
+        assert_eq!(runoff.rounds[2].exhausted, 3);
+        assert_eq!(runoff.winner, Some(a));
+    }
+
+    #[test]
+    fn no_ballots_has_no_winner() {
+        let [a, b] = options();
+        let runoff = tabulate(&[a, b], &[]);
+        assert_eq!(runoff.winner, None);
+        assert_eq!(runoff.rounds.len(), 1);
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Allocation {
+    pub option_id: ObjectId,
+    pub votes: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct QuadraticTally {
+    pub votes: u64,
+    pub credits: u64,
+}
+
+/// Casting `votes` votes on a single option costs `votes²` credits.
+pub fn cost(votes: u32) -> u64 {
+    (votes as u64).pow(2)
+}
+
+/// Credits a whole ballot costs, `None` if the sum doesn't fit in a u64.
+pub fn total_cost(allocations: &[Allocation]) -> Option<u64> {
+    allocations.iter().try_fold(0u64, |spent, allocation| {
+        spent.checked_add(cost(allocation.votes))
+    })
+}
+
+/// Raw votes and credits spent per option across all ballots.
+pub fn tally(ballots: &[Vec<Allocation>]) -> HashMap<ObjectId, QuadraticTally> {
+    let mut tallies: HashMap<ObjectId, QuadraticTally> = HashMap::new();
+    for allocation in ballots.iter().flatten() {
+        let tally = tallies.entry(allocation.option_id).or_default();
+        tally.votes += allocation.votes as u64;
+        tally.credits += cost(allocation.votes);
+    }
+    tallies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credits_grow_quadratically() {
+        let [a, b] = [ObjectId::new(), ObjectId::new()];
+        let ballots = vec![
+            vec![
+                Allocation {
+                    option_id: a,
+                    votes: 3,
+                },
+                Allocation {
+                    option_id: b,
+                    votes: 1,
+                },
+            ],
+            vec![Allocation {
+                option_id: a,
+                votes: 2,
+            }],
+        ];
+        assert_eq!(total_cost(&ballots[0]), Some(10));
+
+        let tallies = tally(&ballots);
+        assert_eq!(
+            tallies[&a],
+            QuadraticTally {
+                votes: 5,
+                credits: 13
+            }
+        );
+        assert_eq!(
+            tallies[&b],
+            QuadraticTally {
+                votes: 1,
+                credits: 1
+            }
+        );
+    }
+
+    #[test]
+    fn oversized_ballots_dont_overflow() {
+        let allocations: Vec<Allocation> = (0..2)
+            .map(|_| Allocation {
+                option_id: ObjectId::new(),
+                votes: u32::MAX,
+            })
+            .collect();
+        assert_eq!(total_cost(&allocations[..1]), Some(cost(u32::MAX)));
+        assert_eq!(total_cost(&allocations), None);
+    }
+}
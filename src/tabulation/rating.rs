@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+
+/// Scores of a rating poll stay within ±`MAX_SCORE` and span at most
+/// `MAX_SCORE_SPAN` points, which bounds the histogram.
+pub const MAX_SCORE: i32 = 1000;
+pub const MAX_SCORE_SPAN: i64 = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistogramBucket {
+    pub score: i32,
+    pub count: u64,
+}
+
+/// Spread of the scores one option received. The statistics are None until
+/// the option has been rated at least once.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RatingSummary {
+    pub count: u64,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    // population standard deviation
+    pub std_dev: Option<f64>,
+    // one bucket per score in the poll's range, lowest first
+    pub histogram: Vec<HistogramBucket>,
+}
+
+pub fn summarize(scores: &[i32], min_score: i32, max_score: i32) -> RatingSummary {
+    // polls created before the range was bounded get no histogram
+    let histogram = if max_score as i64 - min_score as i64 <= MAX_SCORE_SPAN {
+        (min_score..=max_score)
+            .map(|score| HistogramBucket {
+                score,
+                count: scores.iter().filter(|s| **s == score).count() as u64,
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    if scores.is_empty() {
+        return RatingSummary {
+            count: 0,
+            mean: None,
+            median: None,
+            std_dev: None,
+            histogram,
+        };
+    }
+
+    let count = scores.len() as f64;
+    let mean = scores.iter().map(|s| *s as f64).sum::<f64>() / count;
+    let variance = scores
+        .iter()
+        .map(|s| (*s as f64 - mean).powi(2))
+        .sum::<f64>()
+        / count;
+    let mut sorted = scores.to_vec();
+    sorted.sort_unstable();
+    let middle = sorted.len() / 2;
+    let median = if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] as f64 + sorted[middle] as f64) / 2.0
+    } else {
+        sorted[middle] as f64
+    };
+
+    RatingSummary {
+        count: scores.len() as u64,
+        mean: Some(mean),
+        median: Some(median),
+        std_dev: Some(variance.sqrt()),
+        histogram,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_scores() {
+        let summary = summarize(&[5, 3, 4, 4, 1, 5], 1, 5);
+        assert_eq!(summary.count, 6);
+        assert_eq!(summary.mean, Some(22.0 / 6.0));
+        assert_eq!(summary.median, Some(4.0));
+        assert!((summary.std_dev.unwrap() - 1.3744).abs() < 1e-4);
+        let counts: Vec<u64> = summary.histogram.iter().map(|b| b.count).collect();
+        assert_eq!(counts, vec![1, 0, 1, 2, 2]);
+    }
+
+    #[test]
+    fn odd_count_median_is_the_middle_score() {
+        let summary = summarize(&[2, 9, 4], 0, 10);
+        assert_eq!(summary.median, Some(4.0));
+        assert_eq!(summary.histogram.len(), 11);
+    }
+
+    #[test]
+    fn unrated_options_have_no_statistics() {
+        let summary = summarize(&[], 1, 5);
+        assert_eq!(summary.count, 0);
+        assert_eq!(summary.mean, None);
+        assert_eq!(summary.histogram.len(), 5);
+    }
+
+    #[test]
+    fn extreme_scores_dont_overflow() {
+        let summary = summarize(&[i32::MAX, i32::MAX - 1], i32::MIN, i32::MAX);
+        assert_eq!(summary.median, Some(i32::MAX as f64 - 0.5));
+        assert!(summary.histogram.is_empty());
+    }
+}
@@ -0,0 +1,222 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RankedOption {
+    #[serde(rename = "_id")]
+    pub option_id: ObjectId,
+    // options sharing a rank couldn't be separated by either method
+    pub rank: u32,
+    pub schulze_wins: u32,
+    pub copeland_score: i32,
+}
+
+/// `pairwise[i][j]` is the number of ballots preferring `options[i]` over
+/// `options[j]`, `strongest_paths[i][j]` the strength of the strongest path
+/// from `options[i]` to `options[j]`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Schulze {
+    pub options: Vec<ObjectId>,
+    pub pairwise: Vec<Vec<u64>>,
+    pub strongest_paths: Vec<Vec<u64>>,
+    pub ranking: Vec<RankedOption>,
+    pub winner: Option<ObjectId>,
+}
+
+fn pairwise_preferences(options: &[ObjectId], ballots: &[Vec<ObjectId>]) -> Vec<Vec<u64>> {
+    let n = options.len();
+    let mut pairwise = vec![vec![0; n]; n];
+    for ballot in ballots {
+        // position on the ballot, unranked options come last
+        let position: Vec<usize> = options
+            .iter()
+            .map(|id| ballot.iter().position(|ranked| ranked == id).unwrap_or(n))
+            .collect();
+        for i in 0..n {
+            for j in 0..n {
+                if position[i] < position[j] {
+                    pairwise[i][j] += 1;
+                }
+            }
+        }
+    }
+    pairwise
+}
+
+fn strongest_paths(pairwise: &[Vec<u64>]) -> Vec<Vec<u64>> {
+    let n = pairwise.len();
+    let mut paths = vec![vec![0; n]; n];
+    for i in 0..n {
+        for j in 0..n {
+            if i != j && pairwise[i][j] > pairwise[j][i] {
+                paths[i][j] = pairwise[i][j];
+            }
+        }
+    }
+    for k in 0..n {
+        for i in 0..n {
+            if i == k {
+                continue;
+            }
+            for j in 0..n {
+                if j != i && j != k {
+                    paths[i][j] = paths[i][j].max(paths[i][k].min(paths[k][j]));
+                }
+            }
+        }
+    }
+    paths
+}
+
+/// Orders ranked `ballots` by the Schulze method (winning votes). Options are
+/// ranked by how many others they beat on strongest paths; options Schulze
+/// can't separate fall back to their Copeland score (pairwise wins minus
+/// pairwise losses). Whatever is still tied shares a rank and is listed in
+/// poll order, and there is no winner unless a single option ranks first.
+pub fn tabulate(options: &[ObjectId], ballots: &[Vec<ObjectId>]) -> Schulze {
+    let n = options.len();
+    let pairwise = pairwise_preferences(options, ballots);
+    let paths = strongest_paths(&pairwise);
+
+    let scores: Vec<(u32, i32)> = (0..n)
+        .map(|i| {
+            let schulze_wins = (0..n).filter(|&j| paths[i][j] > paths[j][i]).count() as u32;
+            let copeland_score = (0..n)
+                .map(|j| match pairwise[i][j].cmp(&pairwise[j][i]) {
+                    std::cmp::Ordering::Greater => 1,
+                    std::cmp::Ordering::Less => -1,
+                    std::cmp::Ordering::Equal => 0,
+                })
+                .sum();
+            (schulze_wins, copeland_score)
+        })
+        .collect();
+
+    let mut order: Vec<usize> = (0..n).collect();
+    // stable, so tied options keep poll order
+    order.sort_by(|a, b| scores[*b].cmp(&scores[*a]));
+    let ranking: Vec<RankedOption> = order
+        .iter()
+        .map(|&i| RankedOption {
+            option_id: options[i],
+            rank: 1 + scores.iter().filter(|score| **score > scores[i]).count() as u32,
+            schulze_wins: scores[i].0,
+            copeland_score: scores[i].1,
+        })
+        .collect();
+    let winner = match ranking.as_slice() {
+        [first, second, ..] if first.rank == second.rank => None,
+        [first, ..] if !ballots.is_empty() => Some(first.option_id),
+        _ => None,
+    };
+
+    Schulze {
+        options: options.to_vec(),
+        pairwise,
+        strongest_paths: paths,
+        ranking,
+        winner,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ballots(groups: &[(usize, &str)], names: &str, options: &[ObjectId]) -> Vec<Vec<ObjectId>> {
+        groups
+            .iter()
+            .flat_map(|(count, order)| {
+                let ballot: Vec<ObjectId> = order
+                    .chars()
+                    .map(|name| options[names.find(name).unwrap()])
+                    .collect();
+                std::iter::repeat_n(ballot, *count)
+            })
+            .collect()
+    }
+
+    fn order_of(result: &Schulze, names: &str) -> String {
+        result
+            .ranking
+            .iter()
+            .map(|ranked| {
+                let i = result.options.iter().position(|id| *id == ranked.option_id);
+                names.chars().nth(i.unwrap()).unwrap()
+            })
+            .collect()
+    }
+
+    // the 45 voter example from the Schulze method article on Wikipedia
+    #[test]
+    fn matches_the_published_example() {
+        let options: Vec<ObjectId> = (0..5).map(|_| ObjectId::new()).collect();
+        let cast = ballots(
+            &[
+                (5, "ACBED"),
+                (5, "ADECB"),
+                (8, "BEDAC"),
+                (3, "CABED"),
+                (7, "CAEBD"),
+                (2, "CBADE"),
+                (7, "DCEBA"),
+                (8, "EBADC"),
+            ],
+            "ABCDE",
+            &options,
+        );
+        let result = tabulate(&options, &cast);
+
+        assert_eq!(
+            result.pairwise,
+            vec![
+                vec![0, 20, 26, 30, 22],
+                vec![25, 0, 16, 33, 18],
+                vec![19, 29, 0, 17, 24],
+                vec![15, 12, 28, 0, 14],
+                vec![23, 27, 21, 31, 0],
+            ]
+        );
+        assert_eq!(
+            result.strongest_paths,
+            vec![
+                vec![0, 28, 28, 30, 24],
+                vec![25, 0, 28, 33, 24],
+                vec![25, 29, 0, 29, 24],
+                vec![25, 28, 28, 0, 24],
+                vec![25, 28, 28, 31, 0],
+            ]
+        );
+        assert_eq!(order_of(&result, "ABCDE"), "EACBD");
+        assert_eq!(result.winner, Some(options[4]));
+    }
+
+    #[test]
+    fn copeland_separates_a_schulze_tie() {
+        let options: Vec<ObjectId> = (0..4).map(|_| ObjectId::new()).collect();
+        let cast = ballots(
+            &[(1, "CDBA"), (1, "ACBD"), (1, "DBAC"), (1, "DCBA")],
+            "ABCD",
+            &options,
+        );
+        let result = tabulate(&options, &cast);
+
+        // C and D beat the same options on strongest paths and tie head to head
+        assert_eq!(result.strongest_paths[2][3], result.strongest_paths[3][2]);
+        assert_eq!(order_of(&result, "ABCD"), "DCBA");
+        let ranks: Vec<u32> = result.ranking.iter().map(|r| r.rank).collect();
+        assert_eq!(ranks, vec![1, 2, 3, 4]);
+        assert_eq!(result.winner, Some(options[3]));
+    }
+
+    #[test]
+    fn a_perfect_cycle_stays_tied() {
+        let options: Vec<ObjectId> = (0..3).map(|_| ObjectId::new()).collect();
+        let cast = ballots(&[(1, "ABC"), (1, "BCA"), (1, "CAB")], "ABC", &options);
+        let result = tabulate(&options, &cast);
+
+        assert!(result.ranking.iter().all(|ranked| ranked.rank == 1));
+        assert_eq!(order_of(&result, "ABC"), "ABC");
+        assert_eq!(result.winner, None);
+    }
+}
@@ -14,6 +14,9 @@ pub struct Response<T> {
     pub result: Option<T>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    // machine readable reason, e.g. "already_voted"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
 }
 
 impl<T> Response<T>
@@ -25,6 +28,7 @@ where
             status: Status::Ok,
             result: Some(result),
             error: None,
+            code: None,
         });
         HttpResponse::build(status_code).json(response)
     }
@@ -33,6 +37,26 @@ where
             status: Status::Error,
             error: Some(error.to_string()),
             result: None,
+            code: None,
+        });
+        HttpResponse::build(status_code).json(response)
+    }
+    /// An error that still carries a `result`, e.g. the per-row report of a rejected import.
+    pub fn error_with_result(result: T, error: &str, status_code: StatusCode) -> HttpResponse {
+        let response = Json(Response {
+            status: Status::Error,
+            result: Some(result),
+            error: Some(error.to_string()),
+            code: None,
+        });
+        HttpResponse::build(status_code).json(response)
+    }
+    pub fn error_with_code(error: &str, code: &str, status_code: StatusCode) -> HttpResponse {
+        let response: Json<Response<()>> = Json(Response {
+            status: Status::Error,
+            error: Some(error.to_string()),
+            result: None,
+            code: Some(code.to_string()),
         });
         HttpResponse::build(status_code).json(response)
     }
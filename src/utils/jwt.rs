@@ -3,43 +3,78 @@ use std::env;
 use chrono::{Duration, Utc};
 use dotenv::dotenv;
 use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use log::error;
 use serde::{Deserialize, Serialize};
 
+pub const ACCESS_TOKEN_TTL_MINUTES: i64 = 15;
+pub const REFRESH_TOKEN_TTL_DAYS: i64 = 30;
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Claims {
     pub uuid: String,
+    // session the token was issued for, checked on every request
+    pub sid: String,
     pub exp: usize,
 }
 
 impl Claims {
-    pub fn init(uuid: String, exp: usize) -> Self {
+    pub fn init(uuid: String, sid: String, exp: usize) -> Self {
         dotenv().ok();
-        Claims { uuid, exp }
+        Claims { uuid, sid, exp }
     }
 }
 
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RefreshClaims {
+    pub sid: String,
+    pub jti: String,
+    pub exp: usize,
+}
+
 pub struct JWT {
     secret: String,
+    refresh_secret: String,
 }
 
 impl JWT {
     pub fn init() -> Self {
         dotenv().ok();
         let jwt_secret = env::var("JWT_SECRET").expect("Set jwt secret to sign!");
-        JWT { secret: jwt_secret }
+        let refresh_secret = env::var("TOKEN_SECRET").unwrap_or_else(|_| {
+            error!("token_secret var not found!");
+            format!("{}-refresh", jwt_secret)
+        });
+        JWT {
+            secret: jwt_secret,
+            refresh_secret,
+        }
     }
 
-    pub fn sign(&self, uuid: String) -> Result<String, jsonwebtoken::errors::Error> {
+    pub fn sign(&self, uuid: String, sid: String) -> Result<String, jsonwebtoken::errors::Error> {
         let exp = Utc::now()
-            .checked_add_signed(Duration::hours(1))
+            .checked_add_signed(Duration::minutes(ACCESS_TOKEN_TTL_MINUTES))
             .ok_or("Failed to compile exp time")
             .unwrap()
             .timestamp() as usize;
-        let claims = Claims::init(uuid, exp);
+        let claims = Claims::init(uuid, sid, exp);
         let header = &Header::default();
         let encoded_secret = EncodingKey::from_secret(self.secret.as_bytes());
-        let token = encode(header, &claims, &encoded_secret);
-        token
+        encode(header, &claims, &encoded_secret)
+    }
+
+    pub fn sign_refresh(
+        &self,
+        sid: String,
+        jti: String,
+    ) -> Result<String, jsonwebtoken::errors::Error> {
+        let exp = Utc::now()
+            .checked_add_signed(Duration::days(REFRESH_TOKEN_TTL_DAYS))
+            .ok_or("Failed to compile exp time")
+            .unwrap()
+            .timestamp() as usize;
+        let claims = RefreshClaims { sid, jti, exp };
+        let encoded_secret = EncodingKey::from_secret(self.refresh_secret.as_bytes());
+        encode(&Header::default(), &claims, &encoded_secret)
     }
 
     pub fn verify(&self, token: &str) -> bool {
@@ -68,6 +103,20 @@ impl JWT {
         )?;
         Ok(decoded_token.claims)
     }
+
+    pub fn decode_refresh(
+        &self,
+        token: &str,
+    ) -> Result<RefreshClaims, jsonwebtoken::errors::Error> {
+        let mut validations = Validation::new(Algorithm::HS256);
+        validations.validate_exp = true;
+        let decoded_token = decode::<RefreshClaims>(
+            token,
+            &DecodingKey::from_secret(self.refresh_secret.as_ref()),
+            &validations,
+        )?;
+        Ok(decoded_token.claims)
+    }
 }
 
 #[cfg(test)]
@@ -77,7 +126,19 @@ mod tests {
     #[test]
     fn test_sign() {
         let jwt = JWT::init();
-        let token = jwt.sign("test-uuid".to_string());
+        let token = jwt.sign("test-uuid".to_string(), "test-sid".to_string());
         assert!(token.is_ok());
     }
+
+    #[test]
+    fn test_refresh_token_is_not_an_access_token() {
+        let jwt = JWT::init();
+        let token = jwt
+            .sign_refresh("test-sid".to_string(), "test-jti".to_string())
+            .unwrap();
+        let claims = jwt.decode_refresh(&token).unwrap();
+        assert_eq!(claims.sid, "test-sid");
+        assert_eq!(claims.jti, "test-jti");
+        assert!(jwt.decode(&token).is_err());
+    }
 }
@@ -1,6 +1,10 @@
 use log::{debug, error};
 use std::{error::Error, sync::Arc};
-use webauthn_rs::{prelude::Url, Webauthn, WebauthnBuilder};
+use webauthn_rs::{
+    prelude::{CreationChallengeResponse, Url},
+    Webauthn, WebauthnBuilder,
+};
+use webauthn_rs_proto::ResidentKeyRequirement;
 
 use crate::config::app_config::AppConfig;
 
@@ -11,12 +15,11 @@ pub fn config_webauthn(app_config: Arc<AppConfig>) -> Result<Webauthn, Box<dyn E
     debug!("{} {}", rp_id, rp_origin);
 
     // Parse the URL, logging an error if it fails
-    let rp_origin = Url::parse(&rp_origin).map_err(|err| {
+    let rp_origin = Url::parse(rp_origin).inspect_err(|&err| {
         error!("Error parsing rp origin {}: {:?}", rp_origin, err);
-        err
     })?;
 
-    let builder = WebauthnBuilder::new(&rp_id, &rp_origin).map_err(|err| {
+    let builder = WebauthnBuilder::new(rp_id, &rp_origin).map_err(|err| {
         error!("Failed building Webauthn: {:?}", err);
         err
     })?;
@@ -29,3 +32,12 @@ pub fn config_webauthn(app_config: Arc<AppConfig>) -> Result<Webauthn, Box<dyn E
 
     Ok(webauthn)
 }
+
+// Passkey registration asks for a non discoverable credential by default, but
+// usernameless login only works with keys the authenticator stores itself.
+pub fn require_resident_key(ccr: &mut CreationChallengeResponse) {
+    if let Some(selection) = ccr.public_key.authenticator_selection.as_mut() {
+        selection.resident_key = Some(ResidentKeyRequirement::Required);
+        selection.require_resident_key = true;
+    }
+}